@@ -1,29 +1,16 @@
 #import bevy_pbr::forward_io::VertexOutput
 
-#import "./shaders/sdf.wgsl"::{sd_sphere, sd_box, min_sdf, max_sdf, SdfResult}
+#import "./shaders/scene.wgsl"::{map, MAX_DISTANCE}
 
 const MAX_STEPS: i32 = 100;
 const HIT_THRESHOLD: f32 = 0.001;
-const MAX_DISTANCE: f32 = 200.0;
-
-const BLACK: vec3<f32> = vec3(0.0, 0.0, 0.0);
-
-struct GpuPrimative {
-    position: vec3<f32>,
-    is_subtract: u32,
-    scale: vec3<f32>, 
-    blend: f32,
-    color: vec3<f32>,
-    rounding: f32,
-    logical_color: vec3<f32>,
-}
 
 @group(2) @binding(0)
 var<uniform> view_to_world: mat4x4<f32>;
 @group(2) @binding(1)
 var<uniform> clip_to_view: mat4x4<f32>;
-@group(2) @binding(2)
-var<storage, read> primatives: array<GpuPrimative>;
+@group(2) @binding(4)
+var<uniform> step_scale: f32;
 
 fn sky_color(rd: vec3<f32>) -> vec3<f32> {
     let t = clamp(0.5 + 0.5 * rd.y, 0.0, 1.0);
@@ -32,93 +19,15 @@ fn sky_color(rd: vec3<f32>) -> vec3<f32> {
     return mix(horizon, zenith, t);
 }
 
-fn map(p: vec3<f32>) -> SdfResult {
-    var sdf = SdfResult(100.0, BLACK);
-
-    for (var i = 0u; i < arrayLength(&primatives); i++) {
-        let box = primatives[i];
-
-        let color = box.color;
-        let b = sd_box(p - box.position, box.scale, box.rounding, color);
-
-        if (box.is_subtract == 1u) {
-            sdf.dist = op_smooth_subtract(b.dist, sdf.dist, box.blend);
-        } else {
-            sdf = sd_smooth_union(sdf, b, box.blend);
-        }
-        
-    }
-
-    sdf = min_sdf(sdf, sd_ground(p));
-
-    return sdf;
-}
-
-fn sd_ground(p: vec3<f32>) -> SdfResult {
-  return SdfResult(p.y, grid_color(p));
-}
-
-fn grid_color(pos: vec3<f32>) -> vec3<f32> {
-    let minor_scale = 2.5;
-    let major_scale = 0.5;
-    let line_thickness = 0.01;
-    
-    let p_minor = pos.xz * minor_scale;
-    let gx = abs(fract(p_minor.x) - 0.5);
-    let gz = abs(fract(p_minor.y) - 0.5);
-    let line_minor = f32(gx < line_thickness || gz < line_thickness);
-
-    let p_major = pos.xz * major_scale;
-    let mx = abs(fract(p_major.x) - 0.5);
-    let mz = abs(fract(p_major.y) - 0.5);
-    let line_major = f32(mx < line_thickness || mz < line_thickness);
-
-    let base_col   = vec3<f32>(0.95, 0.97, 1.0);
-    let minor_col  = vec3<f32>(0.4, 0.6, 0.9);
-    let major_col  = vec3<f32>(0.1, 0.3, 0.6);
-
-    var col = base_col;
-    if (line_minor > 0.5) { col = minor_col; }
-    if (line_major > 0.5) { col = major_col; }
-
-    return col;
-}
-
-// Cubic polynomial adapted from: https://iquilezles.org/articles/smin/
-fn op_smooth_subtract(s1: f32, s2: f32, k: f32) -> f32 {
-    let n = abs(s1 + s2) / (6.0 * k);
-    let h = 1.0 - min(n, 1.0);
-    let w = h * h * h;
-    let s = w * k;
-
-    return -min(s1, -s2) + s;
-}
-
-// cubic polynomial with color blending. Taken from:
-// https://iquilezles.org/articles/smin/
-fn sd_smooth_union(s1: SdfResult, s2: SdfResult, k: f32) -> SdfResult {
-    let n = abs(s1.dist - s2.dist) / (6.0 * k);
-    let h = 1.0 - min(n, 1.0);
-    let w = h * h * h;
-    let s = w * k;
-    let m = w * 0.5;
-
-    if (s1.dist < s2.dist) {
-        let c = mix(s1.color, s2.color, m);
-        return SdfResult(s1.dist - s, c);
-    } else {
-        let c = mix(s1.color, s2.color, 1.0 - m);
-        return SdfResult(s2.dist - s, c);
-    }
-}
-
 // Lighting method based on Inigo Quilez' raymarching - primatives demo
 // https://www.shadertoy.com/view/Xds3zN
 fn ray_march(camera_origin: vec3<f32>, camera_dir: vec3<f32>) -> vec3<f32> {
     var dist = 0.0;
 
+    // Shorter steps need more of them to cover the same distance
+    let max_steps = i32(f32(MAX_STEPS) / step_scale);
 
-    for (var i = 0; i < MAX_STEPS; i++) {
+    for (var i = 0; i < max_steps; i++) {
         var pos = camera_origin + dist * camera_dir;
         let result = map(pos);
 
@@ -130,7 +39,7 @@ fn ray_march(camera_origin: vec3<f32>, camera_dir: vec3<f32>) -> vec3<f32> {
             return lit_color;
         }
 
-        dist = dist + result.dist;
+        dist = dist + result.dist * step_scale;
 
         if(result.dist > MAX_DISTANCE) {
             break;
@@ -224,7 +133,7 @@ fn soft_shadow(ro: vec3<f32>, rd: vec3<f32>, min_dist: f32, max_dist: f32) -> f3
             return 0.0;
         }
         res = min(res, 16.0 * h / t);
-        t = t + h;
+        t = t + h * step_scale;
         if (t > max_dist) { break; }
     }
     return clamp(res, 0.0, 1.0);
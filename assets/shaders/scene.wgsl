@@ -0,0 +1,108 @@
+#import rust_cad::gpu_types::{GpuPrimative, GpuBvhNode}
+#import "./shaders/sdf.wgsl"::{sd_box, min_sdf, sd_ground, op_smooth_subtract, sd_smooth_subtract, sd_smooth_union, SdfResult}
+
+// The scene's distance field, shared by every pass that marches the scene.
+
+const MAX_DISTANCE: f32 = 200.0;
+
+const BLACK: vec3<f32> = vec3(0.0, 0.0, 0.0);
+
+// GpuPrimative flag bits, mirrored in rendering.rs
+const FLAG_COLOR_CUT: u32 = 1u;
+
+// Primitives whose bounds are further than this from the sample point are
+// not evaluated, their bounds stand in as a lower bound on their distance.
+const BVH_NEAR_DISTANCE: f32 = 1.0;
+const BVH_INTERNAL_NODE: u32 = 0xffffffffu;
+const MAX_NEAR_PRIMATIVES: u32 = 64u;
+
+@group(2) @binding(2)
+var<storage, read> primatives: array<GpuPrimative>;
+@group(2) @binding(3)
+var<storage, read> bvh: array<GpuBvhNode>;
+
+fn map(p: vec3<f32>) -> SdfResult {
+    // Walk the BVH collecting the primitives near p. Subtrees that are far
+    // away are skipped, keeping only the distance to their bounds.
+    var near: array<u32, MAX_NEAR_PRIMATIVES>;
+    var near_count = 0u;
+    var far = MAX_DISTANCE;
+
+    var i = 0u;
+    while (i < arrayLength(&bvh)) {
+        let node = bvh[i];
+        let d = aabb_distance(p, node.min, node.max);
+
+        if (d > BVH_NEAR_DISTANCE) {
+            far = min(far, d);
+            i = node.escape;
+            continue;
+        }
+
+        if (node.primative != BVH_INTERNAL_NODE) {
+            if (near_count == MAX_NEAR_PRIMATIVES) {
+                return map_all(p);
+            }
+
+            near[near_count] = node.primative;
+            near_count++;
+        }
+
+        i++;
+    }
+
+    // CSG operations depend on order, so evaluate in primitive order
+    for (var j = 1u; j < near_count; j++) {
+        let index = near[j];
+        var k = j;
+        while (k > 0u && near[k - 1u] > index) {
+            near[k] = near[k - 1u];
+            k--;
+        }
+        near[k] = index;
+    }
+
+    var sdf = SdfResult(100.0, BLACK);
+
+    for (var j = 0u; j < near_count; j++) {
+        sdf = apply_primative(sdf, p, primatives[near[j]]);
+    }
+
+    sdf.dist = min(sdf.dist, far);
+    sdf = min_sdf(sdf, sd_ground(p));
+
+    return sdf;
+}
+
+// Evaluates every primitive, used when too many are near to collect.
+fn map_all(p: vec3<f32>) -> SdfResult {
+    var sdf = SdfResult(100.0, BLACK);
+
+    for (var i = 0u; i < arrayLength(&primatives); i++) {
+        sdf = apply_primative(sdf, p, primatives[i]);
+    }
+
+    sdf = min_sdf(sdf, sd_ground(p));
+
+    return sdf;
+}
+
+fn apply_primative(sdf: SdfResult, p: vec3<f32>, box: GpuPrimative) -> SdfResult {
+    let color = box.color;
+    let b = sd_box(p - box.position, box.scale, box.rounding_radius, color);
+
+    if (box.is_subtract == 1u) {
+        if ((box.flags & FLAG_COLOR_CUT) != 0u) {
+            return sd_smooth_subtract(b, sdf, box.blend);
+        }
+
+        return SdfResult(op_smooth_subtract(b.dist, sdf.dist, box.blend), sdf.color);
+    }
+
+    return sd_smooth_union(sdf, b, box.blend);
+}
+
+fn aabb_distance(p: vec3<f32>, min_corner: vec3<f32>, max_corner: vec3<f32>) -> f32 {
+    let q = max(min_corner - p, p - max_corner);
+    return length(max(q, vec3(0.0)));
+}
@@ -32,3 +32,79 @@ fn max_sdf(s1: SdfResult, s2: SdfResult) -> SdfResult {
 
     return s2;
 }
+
+fn sd_ground(p: vec3<f32>) -> SdfResult {
+  return SdfResult(p.y, grid_color(p));
+}
+
+fn grid_color(pos: vec3<f32>) -> vec3<f32> {
+    let minor_scale = 2.5;
+    let major_scale = 0.5;
+    let line_thickness = 0.01;
+    
+    let p_minor = pos.xz * minor_scale;
+    let gx = abs(fract(p_minor.x) - 0.5);
+    let gz = abs(fract(p_minor.y) - 0.5);
+    let line_minor = f32(gx < line_thickness || gz < line_thickness);
+
+    let p_major = pos.xz * major_scale;
+    let mx = abs(fract(p_major.x) - 0.5);
+    let mz = abs(fract(p_major.y) - 0.5);
+    let line_major = f32(mx < line_thickness || mz < line_thickness);
+
+    let base_col   = vec3<f32>(0.95, 0.97, 1.0);
+    let minor_col  = vec3<f32>(0.4, 0.6, 0.9);
+    let major_col  = vec3<f32>(0.1, 0.3, 0.6);
+
+    var col = base_col;
+    if (line_minor > 0.5) { col = minor_col; }
+    if (line_major > 0.5) { col = major_col; }
+
+    return col;
+}
+
+// Cubic polynomial adapted from: https://iquilezles.org/articles/smin/
+fn op_smooth_subtract(s1: f32, s2: f32, k: f32) -> f32 {
+    let n = abs(s1 + s2) / (6.0 * k);
+    let h = 1.0 - min(n, 1.0);
+    let w = h * h * h;
+    let s = w * k;
+
+    return -min(s1, -s2) + s;
+}
+
+// Subtraction with color blending, the cut surface takes the cutter's color
+// and blends into the base color across the smooth region.
+fn sd_smooth_subtract(cutter: SdfResult, base: SdfResult, k: f32) -> SdfResult {
+    let n = abs(cutter.dist + base.dist) / (6.0 * k);
+    let h = 1.0 - min(n, 1.0);
+    let w = h * h * h;
+    let s = w * k;
+    let m = w * 0.5;
+
+    if (-cutter.dist > base.dist) {
+        let c = mix(cutter.color, base.color, m);
+        return SdfResult(-cutter.dist + s, c);
+    } else {
+        let c = mix(base.color, cutter.color, m);
+        return SdfResult(base.dist + s, c);
+    }
+}
+
+// cubic polynomial with color blending. Taken from:
+// https://iquilezles.org/articles/smin/
+fn sd_smooth_union(s1: SdfResult, s2: SdfResult, k: f32) -> SdfResult {
+    let n = abs(s1.dist - s2.dist) / (6.0 * k);
+    let h = 1.0 - min(n, 1.0);
+    let w = h * h * h;
+    let s = w * k;
+    let m = w * 0.5;
+
+    if (s1.dist < s2.dist) {
+        let c = mix(s1.color, s2.color, m);
+        return SdfResult(s1.dist - s, c);
+    } else {
+        let c = mix(s1.color, s2.color, 1.0 - m);
+        return SdfResult(s2.dist - s, c);
+    }
+}
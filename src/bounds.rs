@@ -0,0 +1,177 @@
+use bevy::math::bounding::{Aabb3d, BoundingVolume};
+use bevy::prelude::*;
+
+use crate::{geometry, parts, sdf};
+
+/// How many times the candidate region is halved along each axis when
+/// tightening the bounds. The result is at most one cell larger than the
+/// geometry on each side.
+const MAX_DEPTH: u32 = 6;
+
+pub struct BoundsPlugin;
+
+impl Plugin for BoundsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SceneBounds::default())
+            .add_systems(Update, update_scene_bounds);
+    }
+}
+
+/// Conservative axis-aligned bounds of the scene's final CSG result, `None`
+/// when the scene has no solid geometry. Recomputed whenever geometry changes.
+#[derive(Resource, Debug, Default)]
+pub struct SceneBounds(pub Option<Aabb3d>);
+
+type GeometryChanged = Or<(
+    Changed<geometry::BoxGeometry>,
+    Changed<parts::PartInstance>,
+    Changed<parts::PartMembers>,
+)>;
+
+fn update_scene_bounds(
+    changed: Query<(), GeometryChanged>,
+    mut removed_boxes: RemovedComponents<geometry::BoxGeometry>,
+    mut removed_instances: RemovedComponents<parts::PartInstance>,
+    primitives: geometry::ScenePrimitives,
+    mut bounds: ResMut<SceneBounds>,
+) {
+    let removed = removed_boxes.read().count() + removed_instances.read().count() > 0;
+
+    if changed.is_empty() && !removed {
+        return;
+    }
+
+    bounds.0 = compute(&sdf::Scene::new(primitives.collect()));
+}
+
+/// Bounds of everything inside the scene's geometry. Subtraction can only
+/// remove material, so the search starts from the union of the additive
+/// primitives. That region is then subdivided, dropping cells where interval
+/// evaluation of the field proves there is no solid.
+pub fn compute(scene: &sdf::Scene) -> Option<Aabb3d> {
+    let candidate = scene
+        .primitives()
+        .iter()
+        .filter(|p| !p.is_subtract)
+        .map(|p| p.bounds())
+        .reduce(|acc, b| acc.merge(&b))?;
+
+    let mut bounds = None;
+    refine(scene, candidate, 0, &mut bounds);
+
+    bounds
+}
+
+fn refine(scene: &sdf::Scene, cell: Aabb3d, depth: u32, bounds: &mut Option<Aabb3d>) {
+    // Nothing in this cell could grow the bounds
+    if bounds.is_some_and(|b| b.contains(&cell)) {
+        return;
+    }
+
+    let interval = scene.map_interval(cell);
+
+    // Entirely outside the geometry
+    if interval.min > 0.0 {
+        return;
+    }
+
+    // Entirely solid, or as fine as we go
+    if interval.max <= 0.0 || depth == MAX_DEPTH {
+        *bounds = Some(bounds.map_or(cell, |b| b.merge(&cell)));
+        return;
+    }
+
+    let half = cell.half_size() * 0.5;
+    let center = cell.center();
+
+    for i in 0..8 {
+        let offset = Vec3A::new(
+            if i & 1 == 0 { -half.x } else { half.x },
+            if i & 2 == 0 { -half.y } else { half.y },
+            if i & 4 == 0 { -half.z } else { half.z },
+        );
+
+        refine(scene, Aabb3d::new(center + offset, half), depth + 1, bounds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box(id: u32, position: Vec3) -> geometry::BoxGeometry {
+        geometry::BoxGeometry {
+            scale: Vec3::ONE,
+            ..geometry::BoxGeometry::new(position, id)
+        }
+    }
+
+    fn assert_contains(bounds: Aabb3d, min: Vec3, max: Vec3) {
+        assert!(
+            Vec3::from(bounds.min).cmple(min).all() && Vec3::from(bounds.max).cmpge(max).all(),
+            "{bounds:?} doesn't contain {min} {max}"
+        );
+    }
+
+    #[test]
+    fn empty_scene_has_no_bounds() {
+        assert!(compute(&sdf::Scene::default()).is_none());
+
+        let cutter = geometry::BoxGeometry {
+            is_subtract: true,
+            ..unit_box(0, Vec3::ZERO)
+        };
+        assert!(compute(&sdf::Scene::new(vec![cutter])).is_none());
+    }
+
+    #[test]
+    fn bounds_contain_box_and_stay_tight() {
+        let scene = sdf::Scene::new(vec![unit_box(0, Vec3::new(2.0, 1.0, 0.0))]);
+        let bounds = compute(&scene).unwrap();
+
+        assert_contains(bounds, Vec3::new(1.0, 0.0, -1.0), Vec3::new(3.0, 2.0, 1.0));
+        // Within a cell of the true bounds on each side
+        assert!(bounds.half_size().max_element() < 1.1);
+    }
+
+    #[test]
+    fn subtraction_shrinks_bounds() {
+        let base = unit_box(0, Vec3::ZERO);
+        // Removes the top half of the base
+        let cutter = geometry::BoxGeometry {
+            scale: Vec3::new(2.0, 1.0, 2.0),
+            is_subtract: true,
+            ..unit_box(1, Vec3::new(0.0, 1.0, 0.0))
+        };
+
+        let bounds = compute(&sdf::Scene::new(vec![base, cutter])).unwrap();
+
+        assert_contains(
+            bounds,
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+        );
+        assert!(bounds.max.y < 0.2, "{bounds:?}");
+    }
+
+    #[test]
+    fn blending_grows_bounds() {
+        let a = unit_box(0, Vec3::new(-1.2, 0.0, 0.0));
+        let b = geometry::BoxGeometry {
+            blend: 0.5,
+            ..unit_box(1, Vec3::new(1.2, 0.0, 0.0))
+        };
+        let scene = sdf::Scene::new(vec![a, b]);
+        let bounds = compute(&scene).unwrap();
+
+        // Sample the blended field and check every solid point is inside
+        for x in -30..=30 {
+            for y in -30..=30 {
+                let p = Vec3::new(x as f32, y as f32, 0.0) * 0.1;
+                if scene.map_geometry(p).dist <= 0.0 {
+                    assert!(bounds.closest_point(p) == Vec3A::from(p), "{p} outside");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use bevy::math::bounding::{Aabb3d, BoundingVolume};
+use bevy::prelude::*;
+
+/// A node of a bounding volume hierarchy, stored in depth first order so the
+/// tree can be walked without a stack: a node's first child directly follows
+/// it, and `escape` is the index just past its subtree.
+#[derive(Debug, Clone)]
+pub struct BvhNode {
+    pub bounds: Aabb3d,
+    /// Index of the primitive for leaves, `None` for internal nodes.
+    pub primitive: Option<u32>,
+    pub escape: u32,
+}
+
+/// Build a hierarchy over primitive bounds, one primitive per leaf. Nodes
+/// are split at the median along the longest axis of their centers.
+pub fn build(bounds: &[Aabb3d]) -> Vec<BvhNode> {
+    let mut items: Vec<(u32, Aabb3d)> = bounds
+        .iter()
+        .enumerate()
+        .map(|(i, aabb)| (i as u32, *aabb))
+        .collect();
+
+    let mut nodes = Vec::with_capacity(bounds.len() * 2);
+
+    if !items.is_empty() {
+        build_node(&mut items, &mut nodes);
+    }
+
+    nodes
+}
+
+fn build_node(items: &mut [(u32, Aabb3d)], nodes: &mut Vec<BvhNode>) {
+    let bounds = items
+        .iter()
+        .skip(1)
+        .fold(items[0].1, |acc, (_, aabb)| acc.merge(aabb));
+
+    let index = nodes.len();
+    nodes.push(BvhNode {
+        bounds,
+        primitive: None,
+        escape: 0,
+    });
+
+    if let [(primitive, _)] = items {
+        nodes[index].primitive = Some(*primitive);
+    } else {
+        let (min, max) = items.iter().fold(
+            (Vec3A::splat(f32::MAX), Vec3A::splat(f32::MIN)),
+            |(min, max), (_, aabb)| (min.min(aabb.center()), max.max(aabb.center())),
+        );
+
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+            a.center()[axis].total_cmp(&b.center()[axis])
+        });
+
+        let (left, right) = items.split_at_mut(mid);
+        build_node(left, nodes);
+        build_node(right, nodes);
+    }
+
+    nodes[index].escape = nodes.len() as u32;
+}
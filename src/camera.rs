@@ -2,6 +2,7 @@ use bevy::input::mouse::{MouseMotion, MouseWheel};
 use bevy::prelude::*;
 use bevy::render::camera::CameraProjection;
 use bevy::render::view::RenderLayers;
+use serde::{Deserialize, Serialize};
 
 use crate::transform_ext::CameraViewMatrix;
 use crate::{controls, layers, rendering};
@@ -28,12 +29,13 @@ impl Plugin for CameraPlugin {
     }
 }
 
-#[derive(Resource, Debug)]
-struct CameraControls {
-    target: Vec3,
-    azimuth: f32,
-    distance: f32,
-    elevation: f32,
+/// Where the orbiting camera looks from, saved with the scene.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraControls {
+    pub target: Vec3,
+    pub azimuth: f32,
+    pub distance: f32,
+    pub elevation: f32,
 }
 
 impl Default for CameraControls {
@@ -57,6 +59,16 @@ impl CameraControls {
     }
 }
 
+/// The main camera's projection, for a view of the given aspect ratio.
+pub fn projection(aspect_ratio: f32) -> Projection {
+    Projection::from(PerspectiveProjection {
+        fov: std::f32::consts::FRAC_PI_2,
+        aspect_ratio,
+        near: 0.1,
+        far: 1000.0,
+    })
+}
+
 fn setup(mut commands: Commands, mut config_store: ResMut<GizmoConfigStore>) {
     let (config, _) = config_store.config_mut::<DefaultGizmoConfigGroup>();
     config.render_layers = RenderLayers::layer(layers::GIZMOS_LAYER);
@@ -68,12 +80,7 @@ fn setup(mut commands: Commands, mut config_store: ResMut<GizmoConfigStore>) {
             order: layers::GIZMOS_CAMERA,
             ..default()
         },
-        Projection::from(PerspectiveProjection {
-            fov: std::f32::consts::FRAC_PI_2,
-            aspect_ratio: 16.0 / 9.0,
-            near: 0.1,
-            far: 1000.0,
-        }),
+        projection(16.0 / 9.0),
         RenderLayers::layer(layers::GIZMOS_LAYER),
     ));
 }
@@ -145,7 +152,6 @@ fn update_main_camera(
 fn update_material_transform(
     query: Query<(&Transform, &Projection), With<MainCamera>>,
     mut lit_material: ResMut<Assets<rendering::LitMaterial>>,
-    mut selection_material: ResMut<Assets<rendering::SelectionMaterial>>,
 ) {
     let (camera_transform, projection) = query.single().expect("should be one main camera");
 
@@ -153,8 +159,4 @@ fn update_material_transform(
         material.view_to_world = camera_transform.view_matrix().inverse();
         material.clip_to_view = projection.get_clip_from_view().inverse();
     }
-    for (_, material) in selection_material.iter_mut() {
-        material.view_to_world = camera_transform.view_matrix().inverse();
-        material.clip_to_view = projection.get_clip_from_view().inverse();
-    }
 }
@@ -0,0 +1,507 @@
+use std::path::{Path, PathBuf};
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+
+use crate::export::{
+    self, ExportError, ExportOptions, Format, MeshFormat, Mesher, PointsFormat, SectionPlane, Unit,
+    VolumeFormat,
+};
+use crate::scene_file::{LoadError, SceneFile};
+use crate::{bounds, camera, cpu_render, geometry, sdf};
+
+/// The arguments couldn't be understood.
+pub const EXIT_USAGE: i32 = 2;
+/// The scene file couldn't be read or parsed.
+pub const EXIT_LOAD: i32 = 3;
+/// Nothing could be exported, such as for an empty scene.
+pub const EXIT_EXPORT: i32 = 4;
+/// The output couldn't be written.
+pub const EXIT_WRITE: i32 = 5;
+
+const USAGE: &str = "usage: rust-cad export <scene> [-o <output>] [--format <extension>] \
+    [--resolution <cell size>] [--mesher smooth|sharp|adaptive] [--units mm|cm|m|in] \
+    [--plane x|y|z] [--offset <offset>] [--points <count>] [--seed <seed>]
+       rust-cad render <scene> [-o <output>] [--size <width>x<height>]";
+
+/// Image size when rendering without `--size`.
+const DEFAULT_RENDER_SIZE: UVec2 = UVec2::new(512, 512);
+
+/// Run the subcommand named on the command line, if there is one, and
+/// return its exit code. Without one the editor opens as usual.
+pub fn run(args: &[String]) -> Option<i32> {
+    let result = match args.first().map(String::as_str) {
+        Some("export") => export(&args[1..]),
+        Some("render") => render(&args[1..]),
+        _ => return None,
+    };
+
+    Some(match result {
+        Ok(message) => {
+            println!("{message}");
+            0
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            e.code()
+        }
+    })
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Load(LoadError),
+    Export(ExportError),
+    Write(png::EncodingError),
+}
+
+impl CliError {
+    fn code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Load(_) => EXIT_LOAD,
+            CliError::Export(ExportError::Io(_)) => EXIT_WRITE,
+            CliError::Export(_) => EXIT_EXPORT,
+            CliError::Write(_) => EXIT_WRITE,
+        }
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(problem) => write!(f, "{problem}\n{USAGE}"),
+            CliError::Load(e) => e.fmt(f),
+            CliError::Export(e) => e.fmt(f),
+            CliError::Write(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<ExportError> for CliError {
+    fn from(e: ExportError) -> Self {
+        CliError::Export(e)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ExportArgs {
+    scene: PathBuf,
+    output: Option<PathBuf>,
+    format: Option<Format>,
+    cell_size: Option<f32>,
+    mesher: Option<Mesher>,
+    unit: Option<Unit>,
+    plane: Option<usize>,
+    offset: Option<f32>,
+    points: Option<usize>,
+    seed: Option<u64>,
+}
+
+fn parse_export(args: &[String]) -> Result<ExportArgs, CliError> {
+    let usage = |problem: String| CliError::Usage(problem);
+
+    let mut parsed = ExportArgs::default();
+    let mut scene = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if scene.replace(PathBuf::from(arg)).is_some() {
+                return Err(usage(format!("unexpected argument '{arg}'")));
+            }
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| usage(format!("{arg} needs a value")))?;
+        let invalid = || usage(format!("invalid {arg} '{value}'"));
+        let number = || value.parse::<f32>().map_err(|_| invalid());
+
+        match arg.as_str() {
+            "-o" | "--output" => parsed.output = Some(PathBuf::from(value)),
+            "--format" => parsed.format = Some(parse_format(value).ok_or_else(invalid)?),
+            "--resolution" => {
+                let size = number()?;
+                if size <= 0.0 {
+                    return Err(invalid());
+                }
+                parsed.cell_size = Some(size);
+            }
+            "--mesher" => {
+                let mesher = Mesher::ALL
+                    .into_iter()
+                    .find(|m| m.label().eq_ignore_ascii_case(value));
+                parsed.mesher = Some(mesher.ok_or_else(invalid)?);
+            }
+            "--units" => {
+                let unit = Unit::ALL.into_iter().find(|u| u.label() == value);
+                parsed.unit = Some(unit.ok_or_else(invalid)?);
+            }
+            "--plane" => {
+                let axis = SectionPlane::AXIS_LABELS
+                    .iter()
+                    .position(|label| label.eq_ignore_ascii_case(value));
+                parsed.plane = Some(axis.ok_or_else(invalid)?);
+            }
+            "--offset" => parsed.offset = Some(number()?),
+            "--points" => parsed.points = Some(value.parse().map_err(|_| invalid())?),
+            "--seed" => parsed.seed = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(usage(format!("unknown option '{arg}'"))),
+        }
+    }
+
+    parsed.scene = scene.ok_or_else(|| usage("no scene given".to_string()))?;
+    Ok(parsed)
+}
+
+/// Formats by file extension, STL being binary and PLY a mesh unless asked
+/// otherwise.
+fn parse_format(name: &str) -> Option<Format> {
+    if name.eq_ignore_ascii_case("stl-ascii") {
+        return Some(Format::Mesh(MeshFormat::StlAscii));
+    }
+    if name.eq_ignore_ascii_case("points-ply") {
+        return Some(Format::Points(PointsFormat::Ply));
+    }
+
+    Format::ALL
+        .into_iter()
+        .find(|format| format.extension().eq_ignore_ascii_case(name))
+}
+
+/// Load a scene into a world of its own, no window or GPU needed, and
+/// collect its visible nodes.
+fn load(path: &Path) -> Result<(SceneFile, Vec<geometry::SceneNode>), CliError> {
+    let file = SceneFile::load(path).map_err(CliError::Load)?;
+    let mut world = World::new();
+    file.spawn(&mut world);
+
+    let nodes = SystemState::<geometry::ScenePrimitives>::new(&mut world)
+        .get(&world)
+        .collect_nodes();
+
+    Ok((file, nodes))
+}
+
+/// Export a scene as the arguments ask.
+fn export(args: &[String]) -> Result<String, CliError> {
+    let args = parse_export(args)?;
+    let (_, nodes) = load(&args.scene)?;
+
+    let format = args
+        .format
+        .or_else(|| {
+            let extension = args.output.as_ref()?.extension()?.to_str()?;
+            parse_format(extension)
+        })
+        .unwrap_or(Format::Mesh(MeshFormat::StlBinary));
+    let output = args
+        .output
+        .unwrap_or_else(|| args.scene.with_extension(format.extension()));
+
+    let mut options = ExportOptions {
+        format,
+        ..default()
+    };
+    options.mesher = args.mesher.unwrap_or(options.mesher);
+    options.unit = args.unit.unwrap_or(options.unit);
+    options.section.axis = args.plane.unwrap_or(options.section.axis);
+    options.section.offset = args.offset.unwrap_or(options.section.offset);
+    options.points.count = args.points.unwrap_or(options.points.count);
+    options.points.seed = args.seed.unwrap_or(options.points.seed);
+
+    // Layer stacks take the cell size as it is, other formats as a count
+    // over the longest side of the scene, or of the section, which has to
+    // be one the format can be sampled at
+    if let Some(cell_size) = args.cell_size {
+        if format == Format::Volume(VolumeFormat::LayerStack) {
+            if !export::LAYER_SIZE_RANGE.contains(&cell_size) {
+                return Err(CliError::Usage(format!(
+                    "--resolution {cell_size} is out of range; {} takes a layer height and \
+                     pixel size from {} to {}",
+                    format.label(),
+                    export::LAYER_SIZE_RANGE.start(),
+                    export::LAYER_SIZE_RANGE.end(),
+                )));
+            }
+            options.layers.layer_height = cell_size;
+            options.layers.pixel_size = cell_size;
+        }
+
+        let primitives = nodes.iter().flat_map(|node| node.primitives.clone());
+        let bounds = bounds::compute(&sdf::Scene::new(primitives.collect()));
+        if let (Some(bounds), Some(range)) = (bounds, format.resolution_range()) {
+            let longest = match format {
+                Format::Section(_) => {
+                    let (min, max) = options.section.extent(bounds);
+                    (max - min).max_element()
+                }
+                _ => Vec3::from(bounds.max - bounds.min).max_element(),
+            };
+            let resolution = (longest / cell_size).ceil();
+            if !(*range.start() as f32..=*range.end() as f32).contains(&resolution) {
+                return Err(CliError::Usage(format!(
+                    "--resolution {cell_size} would take {resolution} cells across the \
+                     scene's longest side of {longest}; {} takes a cell size from {} to {}",
+                    format.label(),
+                    longest / *range.end() as f32,
+                    longest / *range.start() as f32,
+                )));
+            }
+            options.resolution = resolution as u32;
+        }
+    }
+
+    write(&nodes, &options, &output)
+}
+
+fn write(
+    nodes: &[geometry::SceneNode],
+    options: &ExportOptions,
+    path: &Path,
+) -> Result<String, CliError> {
+    let path_name = path.display();
+    let progress = export::ExportProgress::default();
+
+    let format = match options.format {
+        Format::Mesh(format) => format,
+        Format::Volume(format) => {
+            let summary = export::export_volume(nodes, options, format, path, &progress)?;
+            let [x, y, z] = summary.dims.to_array();
+            return Ok(format!("wrote {x}×{y}×{z} samples to {path_name}"));
+        }
+        Format::Section(format) => {
+            let summary = export::export_section(nodes, options, format, path, &progress)?;
+            return Ok(format!(
+                "wrote {} outlines to {path_name}",
+                summary.contours
+            ));
+        }
+        Format::Points(format) => {
+            let summary = export::export_points(nodes, options, format, path, &progress)?;
+            return Ok(format!("wrote {} points to {path_name}", summary.points));
+        }
+    };
+
+    let prepared = export::prepare(nodes, options, format, &progress)?;
+
+    let report = prepared.report;
+    if !report.is_watertight() || !report.is_manifold() || report.self_intersections > 0 {
+        eprintln!("warning: the mesh has problems: {report:?}");
+    }
+
+    let summary = export::write(&prepared, path, &progress)?;
+    Ok(format!(
+        "wrote {} triangles to {path_name}",
+        summary.triangles
+    ))
+}
+
+#[derive(Debug)]
+struct RenderArgs {
+    scene: PathBuf,
+    output: Option<PathBuf>,
+    size: UVec2,
+}
+
+fn parse_render(args: &[String]) -> Result<RenderArgs, CliError> {
+    let usage = |problem: String| CliError::Usage(problem);
+
+    let mut scene = None;
+    let mut output = None;
+    let mut size = DEFAULT_RENDER_SIZE;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if scene.replace(PathBuf::from(arg)).is_some() {
+                return Err(usage(format!("unexpected argument '{arg}'")));
+            }
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| usage(format!("{arg} needs a value")))?;
+        let invalid = || usage(format!("invalid {arg} '{value}'"));
+
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(value)),
+            "--size" => {
+                let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                let dimension = |text: &str| match text.parse::<u32>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(invalid()),
+                };
+                size = UVec2::new(dimension(width)?, dimension(height)?);
+            }
+            _ => return Err(usage(format!("unknown option '{arg}'"))),
+        }
+    }
+
+    Ok(RenderArgs {
+        scene: scene.ok_or_else(|| usage("no scene given".to_string()))?,
+        output,
+        size,
+    })
+}
+
+/// Render a scene from its saved camera on the CPU and write a PNG.
+fn render(args: &[String]) -> Result<String, CliError> {
+    let args = parse_render(args)?;
+    let (file, nodes) = load(&args.scene)?;
+
+    let output = args
+        .output
+        .unwrap_or_else(|| args.scene.with_extension("png"));
+
+    let controls = file.camera.unwrap_or_default();
+    let UVec2 {
+        x: width,
+        y: height,
+    } = args.size;
+    let camera = geometry::CameraRays::new(
+        &camera::projection(width as f32 / height as f32),
+        &controls.transform(),
+    );
+
+    let scene = sdf::Scene::new(nodes.into_iter().flat_map(|node| node.primitives).collect());
+    cpu_render::render(&scene, &camera, width, height)
+        .write_png(&output)
+        .map_err(CliError::Write)?;
+
+    Ok(format!(
+        "wrote {width}×{height} image to {}",
+        output.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scene_file::{BoxRecord, SceneFile};
+
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parses_export_options() {
+        let parsed = parse_export(&args(
+            "part.scene --format stl-ascii --resolution 0.2 -o out.stl",
+        ))
+        .unwrap();
+
+        assert_eq!(parsed.scene, PathBuf::from("part.scene"));
+        assert_eq!(parsed.format, Some(Format::Mesh(MeshFormat::StlAscii)));
+        assert_eq!(parsed.cell_size, Some(0.2));
+        assert_eq!(parsed.output, Some(PathBuf::from("out.stl")));
+
+        for bad in [
+            "",
+            "part.scene --format nope",
+            "a b",
+            "part.scene --resolution",
+        ] {
+            assert!(matches!(parse_export(&args(bad)), Err(CliError::Usage(_))));
+        }
+    }
+
+    #[test]
+    fn parses_render_sizes() {
+        let parsed = parse_render(&args("part.scene --size 64x36")).unwrap();
+        assert_eq!(parsed.size, UVec2::new(64, 36));
+        assert_eq!(parsed.output, None);
+
+        for bad in [
+            "part.scene --size 64",
+            "part.scene --size 0x36",
+            "--size 1x1",
+        ] {
+            assert!(matches!(parse_render(&args(bad)), Err(CliError::Usage(_))));
+        }
+    }
+
+    #[test]
+    fn renders_scenes_to_png() {
+        let dir = std::env::temp_dir().join("raystacean_cli_render");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let scene = dir.join("box.scene");
+        let file = SceneFile {
+            boxes: vec![BoxRecord::from(&geometry::BoxGeometry::new(Vec3::ZERO, 0))],
+            ..default()
+        };
+        std::fs::write(&scene, ron::to_string(&file).unwrap()).unwrap();
+
+        let code = run(&args(&format!("render {} --size 32x16", scene.display())));
+        assert_eq!(code, Some(0));
+
+        let decoder = png::Decoder::new(std::fs::File::open(dir.join("box.png")).unwrap());
+        let info = decoder.read_info().unwrap().info().clone();
+        assert_eq!((info.width, info.height), (32, 16));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exit_codes_tell_loading_from_exporting() {
+        let dir = std::env::temp_dir().join("raystacean_cli_exit_codes");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let scene = |name: &str, file: SceneFile| {
+            let path = dir.join(name);
+            std::fs::write(&path, ron::to_string(&file).unwrap()).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let run_export = |line: String| run(&args(&format!("export {line}")));
+
+        let boxed = scene(
+            "box.scene",
+            SceneFile {
+                boxes: vec![BoxRecord::from(&geometry::BoxGeometry::new(Vec3::ZERO, 0))],
+                ..default()
+            },
+        );
+        let empty = scene("empty.scene", SceneFile::default());
+        let output = dir.join("box.stl");
+
+        assert_eq!(
+            run_export(format!("{boxed} --resolution 0.5 -o {}", output.display())),
+            Some(0)
+        );
+        assert!(std::fs::metadata(&output).unwrap().len() > 84);
+
+        let missing = dir.join("missing.scene");
+        assert_eq!(run_export(missing.display().to_string()), Some(EXIT_LOAD));
+        assert_eq!(run_export(empty), Some(EXIT_EXPORT));
+        assert_eq!(run_export(format!("{boxed} --bogus 1")), Some(EXIT_USAGE));
+        assert_eq!(
+            run_export(format!("{boxed} --resolution 0.0001")),
+            Some(EXIT_USAGE)
+        );
+        assert_eq!(
+            run_export(format!("{boxed} --resolution 10")),
+            Some(EXIT_USAGE)
+        );
+        assert_eq!(
+            run_export(format!("{boxed} --format svg --resolution 0.5")),
+            Some(0)
+        );
+        assert_eq!(
+            run_export(format!("{boxed} --format svg --resolution 0.0001")),
+            Some(EXIT_USAGE)
+        );
+        assert_eq!(
+            run_export(format!("{boxed} --format zip --resolution 0.0001")),
+            Some(EXIT_USAGE)
+        );
+        assert_eq!(run(&args("view")), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
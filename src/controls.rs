@@ -17,6 +17,7 @@ impl Plugin for ControlContextPlugin {
 pub enum ControlMode {
     Select,
     PlaceGeometry,
+    PlacePart(Entity),
 }
 
 #[derive(Resource, Debug, PartialEq, Eq, Clone, Copy)]
@@ -36,7 +37,7 @@ impl ControlMode {
     pub fn selection_policy(&self) -> SelectionPolicy {
         match self {
             ControlMode::Select => SelectionPolicy::Single,
-            ControlMode::PlaceGeometry => SelectionPolicy::None,
+            ControlMode::PlaceGeometry | ControlMode::PlacePart(_) => SelectionPolicy::None,
         }
     }
 }
@@ -0,0 +1,251 @@
+use std::{fs::File, io::BufWriter, path::Path, sync::Mutex};
+
+use bevy::prelude::*;
+
+use crate::{geometry, sdf};
+
+// Mirrors of the constants in lit_shader.wgsl
+const MAX_STEPS: i32 = 100;
+const HIT_THRESHOLD: f32 = 0.001;
+const MAX_DISTANCE: f32 = 200.0;
+
+/// An sRGB encoded RGBA8 image, rows ordered top to bottom.
+#[derive(Debug, Clone)]
+pub struct RenderedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RenderedImage {
+    #[cfg(test)]
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    pub fn write_png(&self, path: impl AsRef<Path>) -> Result<(), png::EncodingError> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)
+    }
+}
+
+/// Render the scene the way the lit shader does, as a reference that
+/// doesn't need a GPU. Rows are shared out between all available threads.
+pub fn render(
+    scene: &sdf::Scene,
+    camera: &geometry::CameraRays,
+    width: u32,
+    height: u32,
+) -> RenderedImage {
+    let mut pixels = vec![0; (width * height * 4) as usize];
+    let size = Vec2::new(width as f32, height as f32);
+
+    let rows = Mutex::new(pixels.chunks_mut(width as usize * 4).enumerate());
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    std::thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                loop {
+                    let Some((y, row)) = rows.lock().expect("render thread panicked").next() else {
+                        break;
+                    };
+
+                    for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                        // Sample at the pixel center like the fragment shader
+                        let position = Vec2::new(x as f32, y as f32) + 0.5;
+                        let ray = camera.through_screen(position, size);
+
+                        let color = ray_march(scene, ray.origin, ray.direction.as_vec3());
+                        let color = color.clamp(Vec3::ZERO, Vec3::ONE);
+
+                        pixel.copy_from_slice(
+                            &Color::linear_rgb(color.x, color.y, color.z)
+                                .to_srgba()
+                                .to_u8_array(),
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    RenderedImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+fn sky_color(rd: Vec3) -> Vec3 {
+    let t = (0.5 + 0.5 * rd.y).clamp(0.0, 1.0);
+    let horizon = Vec3::new(0.8, 0.9, 1.0);
+    let zenith = Vec3::new(0.4, 0.6, 0.9);
+    horizon.lerp(zenith, t)
+}
+
+// Lighting method based on Inigo Quilez' raymarching - primatives demo
+// https://www.shadertoy.com/view/Xds3zN
+fn ray_march(scene: &sdf::Scene, camera_origin: Vec3, camera_dir: Vec3) -> Vec3 {
+    let mut dist = 0.0;
+
+    for _ in 0..MAX_STEPS {
+        let pos = camera_origin + dist * camera_dir;
+        let result = scene.map(pos);
+
+        // Hit something
+        if result.dist < HIT_THRESHOLD {
+            return calc_lighting(scene, pos, result.color, camera_dir);
+        }
+
+        dist += result.dist;
+
+        if result.dist > MAX_DISTANCE {
+            break;
+        }
+    }
+
+    // Sky color
+    sky_color(camera_dir)
+}
+
+fn calc_lighting(scene: &sdf::Scene, pos: Vec3, color_in: Vec3, camera_dir: Vec3) -> Vec3 {
+    let sun_dir = Vec3::new(-0.5, 0.4, -0.6).normalize();
+    let half_dir = (sun_dir - camera_dir).normalize();
+
+    let normal = scene.normal(pos);
+    let reflected = reflect(camera_dir, normal);
+
+    let mut color = Vec3::ZERO;
+
+    {
+        // diffuse
+        let mut diff = normal.dot(sun_dir).clamp(0.0, 1.0);
+        diff *= soft_shadow(scene, pos, sun_dir, 0.02, 2.5);
+        // Blinn-phong Specular
+        let mut spec = normal.dot(half_dir).max(0.0).powf(16.0);
+        spec *= diff;
+        // Fresnel
+        spec *= 0.04 + 0.96 * (1.0 - half_dir.dot(sun_dir)).clamp(0.0, 1.0).powf(5.0);
+
+        let diffuse_color = color_in * 1.8 * diff * Vec3::new(1.30, 1.00, 0.70);
+        let specular_color = 5.00 * spec * Vec3::new(1.30, 1.0, 0.7);
+        color += diffuse_color + specular_color;
+    }
+
+    // Sky light
+    {
+        // diff
+        let diff = (0.5 + 0.5 * normal.y).clamp(0.0, 1.0).sqrt();
+        let mut spec = smoothstep(-0.2, 0.2, reflected.y);
+        spec *= diff;
+        // Fresnel
+        spec *= 0.04 + 0.96 * (1.0 + normal.dot(camera_dir)).clamp(0.0, 1.0).powf(5.0);
+        spec *= soft_shadow(scene, pos, reflected, 0.02, 2.5);
+
+        color += color_in * 0.4 * diff * Vec3::new(0.4, 0.6, 1.15);
+        color += 1.00 * spec * Vec3::new(0.4, 0.6, 1.30);
+    }
+
+    color
+}
+
+fn soft_shadow(scene: &sdf::Scene, ro: Vec3, rd: Vec3, min_dist: f32, max_dist: f32) -> f32 {
+    let mut t = min_dist;
+    let mut res: f32 = 1.0;
+
+    for _ in 0..32 {
+        let h = scene.map(ro + rd * t).dist;
+        if h < 0.001 {
+            return 0.0;
+        }
+        res = res.min(16.0 * h / t);
+        t += h;
+        if t > max_dist {
+            break;
+        }
+    }
+
+    res.clamp(0.0, 1.0)
+}
+
+fn reflect(i: Vec3, n: Vec3) -> Vec3 {
+    i - 2.0 * n.dot(i) * n
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera;
+    use crate::node_id::NodeId;
+
+    fn camera(width: u32, height: u32) -> geometry::CameraRays {
+        let projection = camera::projection(width as f32 / height as f32);
+        let transform = Transform::from_xyz(6.0, 6.0, 6.0).looking_at(Vec3::Y, Vec3::Y);
+
+        geometry::CameraRays::new(&projection, &transform)
+    }
+
+    #[test]
+    fn center_ray_follows_camera_forward() {
+        let transform = Transform::from_xyz(6.0, 6.0, 6.0).looking_at(Vec3::Y, Vec3::Y);
+        let ray = camera(64, 36).through_ndc(Vec2::ZERO);
+
+        assert!(ray.origin.distance(transform.translation) < 1e-4);
+        assert!(ray.direction.dot(*transform.forward()) > 0.9999);
+    }
+
+    #[test]
+    fn renders_box_against_sky_and_ground() {
+        let (width, height) = (64, 36);
+        let scene = sdf::Scene::new(vec![geometry::BoxGeometry {
+            position: Vec3::Y,
+            scale: Vec3::ONE,
+            color: [1.0, 0.0, 0.0],
+            rounding: 0.0,
+            blend: 0.0,
+            is_subtract: false,
+            color_cut: false,
+            visible: true,
+            locked: false,
+            isolated: false,
+            id: NodeId::new(0),
+        }]);
+
+        let image = render(&scene, &camera(width, height), width, height);
+
+        // Looking down at the box, it fills the middle of the frame
+        let [r, g, b, a] = image.pixel(width / 2, height / 2);
+        assert_eq!(a, 255);
+        assert!(r > g && r > b, "expected a red box, got {r} {g} {b}");
+
+        // Sky above the horizon
+        let [r, _, b, _] = image.pixel(width / 2, 0);
+        assert!(b > r, "expected sky, got {r} {b}");
+
+        // The ground is bright and white, where the sky near the horizon
+        // is as bright but tinted blue
+        let [r, g, b, _] = image.pixel(2, height - 2);
+        assert!(
+            r > 200 && r.abs_diff(g) <= 4 && r.abs_diff(b) <= 4,
+            "expected ground, got {r} {g} {b}"
+        );
+    }
+}
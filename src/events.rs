@@ -1,8 +1,5 @@
 use bevy::prelude::*;
 
-#[derive(Event, Debug)]
-pub struct PixelColorUnderCursor(Vec3);
-
 #[derive(Event, Debug)]
 pub struct PlaneClicked;
 
@@ -11,6 +8,12 @@ pub struct GeometryAdded {
     pub entity: Entity,
 }
 
+/// Request to select a scene node directly, e.g. from the scene list.
+#[derive(Event, Debug)]
+pub struct NodeSelected {
+    pub entity: Entity,
+}
+
 #[derive(Event, Debug)]
 pub struct OriginDragged {
     pub axis: Vec3,
@@ -22,13 +25,3 @@ pub struct ScalingGizmoDragged {
     pub axis: Vec3,
     pub delta: Vec2,
 }
-
-impl PixelColorUnderCursor {
-    pub fn new(color: Vec3) -> Self {
-        Self(color)
-    }
-
-    pub fn color(&self) -> Vec3 {
-        self.0
-    }
-}
@@ -0,0 +1,866 @@
+use std::io;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use bevy::color::palettes::css::{AQUA, ORANGE};
+use bevy::math::bounding::{Aabb3d, BoundingVolume};
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task, block_on};
+
+use crate::{bounds, geometry, node_id, sdf};
+
+mod decimate;
+mod dual_contouring;
+mod gltf;
+mod grid;
+mod layer_stack;
+mod marching_cubes;
+mod marching_squares;
+mod mesh;
+mod obj;
+mod octree;
+mod ply;
+mod points;
+mod section;
+mod smooth;
+mod stl;
+mod threemf;
+mod validate;
+mod volume;
+
+pub use mesh::{
+    Decimation, MeshFormat, MeshReport, Mesher, PreparedExport, Smoothing, prepare, write,
+};
+pub use points::{PointSampling, PointsFormat, export_points};
+pub use section::{SectionFormat, SectionPlane, export_section};
+pub use volume::{LAYER_SIZE_RANGE, LayerSlicing, VolumeFormat, export_volume};
+
+pub const MIN_RESOLUTION: u32 = 8;
+pub const MAX_RESOLUTION: u32 = 512;
+
+/// What the field reads once an export is cancelled, far enough out that
+/// nothing is left to mesh.
+const CANCELLED_DISTANCE: f32 = 1.0e9;
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ExportDialog::default()).add_systems(
+            Update,
+            (
+                discard_stale_check,
+                finish_pending,
+                draw_section_plane,
+                draw_preview,
+            ),
+        );
+    }
+}
+
+/// An indexed triangle mesh, wound counter-clockwise seen from outside.
+#[derive(Debug, Clone, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+    /// Linear color per vertex, empty until sampled.
+    pub colors: Vec<Vec3>,
+    /// Normal per vertex, empty until sampled.
+    pub normals: Vec<Vec3>,
+    /// Color of the primitive each triangle came from, empty until sampled.
+    pub face_colors: Vec<Vec3>,
+}
+
+impl TriangleMesh {
+    /// Take each vertex's color from the scene, blends included.
+    pub fn sample_colors(&mut self, scene: &sdf::Scene) {
+        self.colors = self
+            .positions
+            .iter()
+            .map(|p| scene.map_geometry(*p).color)
+            .collect();
+    }
+
+    /// Take each vertex's normal from the gradient of the scene's geometry.
+    pub fn sample_normals(&mut self, scene: &sdf::Scene) {
+        self.normals = self
+            .positions
+            .iter()
+            .map(|p| scene.geometry_normal(*p))
+            .collect();
+    }
+
+    /// Color each triangle after the primitive whose surface it lies on.
+    pub fn sample_face_colors(&mut self, scene: &sdf::Scene) {
+        self.face_colors = self
+            .triangles
+            .iter()
+            .map(|triangle| {
+                let center = triangle
+                    .iter()
+                    .map(|i| self.positions[*i as usize])
+                    .sum::<Vec3>()
+                    / 3.0;
+
+                scene
+                    .nearest_primitive(center, shows_color)
+                    .map_or(Vec3::ONE, |b| Vec3::from(b.color))
+            })
+            .collect();
+    }
+
+    /// Add another mesh's vertices and triangles to this one.
+    pub fn append(&mut self, other: TriangleMesh) {
+        let offset = self.positions.len() as u32;
+
+        self.positions.extend(other.positions);
+        self.colors.extend(other.colors);
+        self.normals.extend(other.normals);
+        self.face_colors.extend(other.face_colors);
+        self.triangles
+            .extend(other.triangles.iter().map(|t| t.map(|i| i + offset)));
+    }
+
+    /// Each edge once, as its two end points.
+    pub fn edges(&self) -> Vec<[Vec3; 2]> {
+        let mut edges: Vec<(u32, u32)> = self
+            .triangles
+            .iter()
+            .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+
+        edges
+            .into_iter()
+            .map(|(a, b)| [self.positions[a as usize], self.positions[b as usize]])
+            .collect()
+    }
+
+    /// Convert from the scene's Y up to the Z up expected by printers and
+    /// most CAD tools.
+    pub fn into_z_up(mut self) -> Self {
+        for p in self.positions.iter_mut().chain(&mut self.normals) {
+            *p = Vec3::new(p.x, -p.z, p.y);
+        }
+
+        self
+    }
+}
+
+/// What an export writes, grouped by what the file holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mesh(MeshFormat),
+    Volume(VolumeFormat),
+    Section(SectionFormat),
+    Points(PointsFormat),
+}
+
+impl Format {
+    pub const ALL: [Format; 13] = [
+        Format::Mesh(MeshFormat::StlBinary),
+        Format::Mesh(MeshFormat::StlAscii),
+        Format::Mesh(MeshFormat::Glb),
+        Format::Mesh(MeshFormat::Obj),
+        Format::Mesh(MeshFormat::Ply),
+        Format::Mesh(MeshFormat::ThreeMf),
+        Format::Volume(VolumeFormat::Raw),
+        Format::Volume(VolumeFormat::Vox),
+        Format::Section(SectionFormat::Svg),
+        Format::Section(SectionFormat::Dxf),
+        Format::Volume(VolumeFormat::LayerStack),
+        Format::Points(PointsFormat::Ply),
+        Format::Points(PointsFormat::Xyz),
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Format::Mesh(format) => format.label(),
+            Format::Volume(format) => format.label(),
+            Format::Section(format) => format.label(),
+            Format::Points(format) => format.label(),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Mesh(format) => format.extension(),
+            Format::Volume(format) => format.extension(),
+            Format::Section(format) => format.extension(),
+            Format::Points(format) => format.extension(),
+        }
+    }
+
+    /// Whether the file says what length a unit is.
+    pub fn records_units(self) -> bool {
+        matches!(self, Format::Mesh(MeshFormat::ThreeMf) | Format::Section(_))
+    }
+
+    /// The cell counts along the longest side that the format is sampled at,
+    /// the longest side of the section for sections, or `None` when it's
+    /// sampled by cell size instead.
+    pub fn resolution_range(self) -> Option<RangeInclusive<u32>> {
+        match self {
+            Format::Volume(VolumeFormat::LayerStack) => None,
+            Format::Volume(VolumeFormat::Vox) => Some(MIN_RESOLUTION..=volume::VOX_MAX_SIZE),
+            Format::Mesh(_)
+            | Format::Volume(VolumeFormat::Raw)
+            | Format::Section(_)
+            | Format::Points(_) => Some(MIN_RESOLUTION..=MAX_RESOLUTION),
+        }
+    }
+}
+
+/// The length of one world unit, for formats that record it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Millimeter,
+    Centimeter,
+    Meter,
+    Inch,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 4] = [Unit::Millimeter, Unit::Centimeter, Unit::Meter, Unit::Inch];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Unit::Millimeter => "mm",
+            Unit::Centimeter => "cm",
+            Unit::Meter => "m",
+            Unit::Inch => "in",
+        }
+    }
+
+    /// Name of the unit in 3MF.
+    pub fn name(self) -> &'static str {
+        match self {
+            Unit::Millimeter => "millimeter",
+            Unit::Centimeter => "centimeter",
+            Unit::Meter => "meter",
+            Unit::Inch => "inch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub format: Format,
+    pub mesher: Mesher,
+    /// Number of cells along the longest axis of the scene bounds. For the
+    /// adaptive mesher, the finest cells it will subdivide to.
+    pub resolution: u32,
+    /// How far the adaptive mesher may move the surface when merging cells,
+    /// in world units.
+    pub max_error: f32,
+    /// Mesh each body on its own and keep them apart in the file, when the
+    /// format allows.
+    pub separate_bodies: bool,
+    pub unit: Unit,
+    pub smoothing: Smoothing,
+    pub smoothing_iterations: u32,
+    pub decimation: Option<Decimation>,
+    pub section: SectionPlane,
+    pub layers: LayerSlicing,
+    pub points: PointSampling,
+    pub scope: Scope,
+    /// The selected nodes, used when exporting the selection.
+    pub selection: Vec<node_id::NodeId>,
+    /// Write each body to a file of its own, named after its node.
+    pub file_per_body: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: Format::Mesh(MeshFormat::StlBinary),
+            mesher: Mesher::Sharp,
+            resolution: 128,
+            max_error: 0.01,
+            separate_bodies: false,
+            unit: Unit::Millimeter,
+            smoothing: Smoothing::Off,
+            smoothing_iterations: 5,
+            decimation: None,
+            section: SectionPlane::default(),
+            layers: LayerSlicing::default(),
+            points: PointSampling::default(),
+            scope: Scope::Scene,
+            selection: Vec::new(),
+            file_per_body: false,
+        }
+    }
+}
+
+/// Which nodes are exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Scene,
+    /// The selected nodes alone.
+    Selection,
+    /// The selected nodes, still cut by the nodes around them.
+    SelectionWithCuts,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Scene, Scope::Selection, Scope::SelectionWithCuts];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Scope::Scene => "Whole scene",
+            Scope::Selection => "Selection",
+            Scope::SelectionWithCuts => "Selection with cuts",
+        }
+    }
+
+    /// The nodes to export. Unselected nodes either drop out or keep only
+    /// the primitives that cut.
+    pub fn nodes(
+        self,
+        nodes: &[geometry::SceneNode],
+        selection: &[node_id::NodeId],
+    ) -> Vec<geometry::SceneNode> {
+        let selected = |node: &geometry::SceneNode| selection.contains(&node.id);
+
+        match self {
+            Scope::Scene => nodes.to_vec(),
+            Scope::Selection => nodes.iter().filter(|n| selected(n)).cloned().collect(),
+            Scope::SelectionWithCuts => nodes
+                .iter()
+                .map(|node| {
+                    let mut node = node.clone();
+                    if !selected(&node) {
+                        node.primitives.retain(|p| p.is_subtract);
+                    }
+                    node
+                })
+                .collect(),
+        }
+    }
+}
+
+/// State of the export dialog opened from the File menu.
+#[derive(Resource, Debug)]
+pub struct ExportDialog {
+    pub open: bool,
+    pub path: String,
+    pub options: ExportOptions,
+    /// The mesh last checked, saved as is while the options and scene
+    /// stay the same.
+    pub prepared: Option<PreparedExport>,
+    /// Edges of the checked mesh, drawn over the scene.
+    pub preview: Preview,
+    pub show_preview: bool,
+    /// A check or export still running in the background.
+    pub pending: Option<PendingExport>,
+    /// Outcome of the last export, shown in the dialog.
+    pub status: Option<Result<String, String>>,
+}
+
+/// Edges of a mesh, as their end points.
+pub type Preview = Vec<[Vec3; 2]>;
+
+/// A check or export running on the async compute pool.
+#[derive(Debug)]
+pub struct PendingExport {
+    pub progress: Arc<ExportProgress>,
+    /// Whether this is a check, which goes stale when the scene changes.
+    is_check: bool,
+    task: Task<Result<Finished, ExportError>>,
+}
+
+/// What a background job hands back.
+#[derive(Debug)]
+enum Finished {
+    /// A mesh checked by [`prepare`], to be saved later.
+    Checked(PreparedExport, Preview),
+    /// A file written, with a line saying what went into it.
+    Written(String),
+}
+
+/// How far along an export is, shared with whoever is waiting on it so
+/// they can also ask it to stop.
+#[derive(Debug)]
+pub struct ExportProgress {
+    /// Fraction done, as the bits of an `f32`.
+    done: AtomicU32,
+    /// The part of the whole the current stage fills, as the bits of the
+    /// `f32`s it starts and ends at.
+    stage: [AtomicU32; 2],
+    cancelled: AtomicBool,
+}
+
+impl Default for ExportProgress {
+    fn default() -> Self {
+        Self {
+            done: AtomicU32::new(0.0f32.to_bits()),
+            stage: [0.0f32, 1.0].map(|f| AtomicU32::new(f.to_bits())),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+}
+
+impl ExportProgress {
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.done.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, fraction: f32) {
+        self.done.store(fraction.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Start a stage filling `range` of the whole, which whatever does the
+    /// work steps through with [`Self::advance`].
+    fn stage(&self, range: Range<f32>) {
+        self.stage[0].store(range.start.to_bits(), Ordering::Relaxed);
+        self.stage[1].store(range.end.to_bits(), Ordering::Relaxed);
+        self.set(range.start);
+    }
+
+    fn current_stage(&self) -> Range<f32> {
+        let [start, end] = self
+            .stage
+            .each_ref()
+            .map(|f| f32::from_bits(f.load(Ordering::Relaxed)));
+
+        start..end
+    }
+
+    /// Record `done` steps of `total` through the current stage.
+    fn advance(&self, done: usize, total: usize) {
+        let Range { start, end } = self.current_stage();
+        let part = done as f32 / total.max(1) as f32;
+
+        self.set(start + (end - start) * part.min(1.0));
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn check(&self) -> Result<(), ExportError> {
+        if self.is_cancelled() {
+            Err(ExportError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `field` until cancelled, then empty space everywhere, so whatever is
+    /// sampling it runs out of surface to follow and finishes early.
+    fn guard(&self, field: impl Fn(Vec3) -> f32 + Sync) -> impl Fn(Vec3) -> f32 + Sync {
+        move |p| {
+            if self.is_cancelled() {
+                CANCELLED_DISTANCE
+            } else {
+                field(p)
+            }
+        }
+    }
+}
+
+impl ExportDialog {
+    /// Change format, keeping the file extension in step.
+    pub fn set_format(&mut self, format: Format) {
+        if self.options.format == format {
+            return;
+        }
+
+        self.options.format = format;
+        self.path = Path::new(&self.path)
+            .with_extension(format.extension())
+            .to_string_lossy()
+            .into_owned();
+    }
+
+    /// Start meshing `nodes` for `format` in the background, cancelling
+    /// anything already running.
+    pub fn start_check(&mut self, nodes: Vec<geometry::SceneNode>, format: MeshFormat) {
+        let options = self.options.clone();
+
+        self.spawn(true, move |progress| {
+            let prepared = prepare(&nodes, &options, format, progress)?;
+            let preview = prepared
+                .meshes
+                .iter()
+                .flat_map(|(_, mesh)| mesh.edges())
+                .collect();
+
+            Ok(Finished::Checked(prepared, preview))
+        });
+    }
+
+    /// Write `nodes` to the dialog's path in the background with `export`,
+    /// which says what it wrote. Anything already running is cancelled.
+    pub fn start_export(
+        &mut self,
+        nodes: Vec<geometry::SceneNode>,
+        export: impl FnOnce(
+            &[geometry::SceneNode],
+            &ExportOptions,
+            &Path,
+            &ExportProgress,
+        ) -> Result<String, ExportError>
+        + Send
+        + 'static,
+    ) {
+        let options = self.options.clone();
+        let path = PathBuf::from(&self.path);
+
+        self.spawn(false, move |progress| {
+            export(&nodes, &options, &path, progress).map(Finished::Written)
+        });
+    }
+
+    fn spawn(
+        &mut self,
+        is_check: bool,
+        job: impl FnOnce(&ExportProgress) -> Result<Finished, ExportError> + Send + 'static,
+    ) {
+        self.cancel();
+
+        let progress = Arc::new(ExportProgress::default());
+        let shared = progress.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move { job(&shared) });
+
+        self.pending = Some(PendingExport {
+            progress,
+            is_check,
+            task,
+        });
+        self.status = None;
+    }
+
+    /// Stop whatever is running in the background.
+    pub fn cancel(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.progress.cancel();
+        }
+    }
+
+    /// Forget the checked mesh, it no longer matches what would be exported.
+    pub fn discard_check(&mut self) {
+        self.prepared = None;
+        self.preview.clear();
+    }
+}
+
+impl Default for ExportDialog {
+    fn default() -> Self {
+        Self {
+            open: false,
+            path: "export.stl".to_string(),
+            options: ExportOptions::default(),
+            prepared: None,
+            preview: Vec::new(),
+            show_preview: true,
+            pending: None,
+            status: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    /// The scene has no solid geometry to export.
+    EmptyScene,
+    /// The section plane misses the geometry.
+    EmptySection,
+    Cancelled,
+    Io(io::Error),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::EmptyScene => write!(f, "the scene has no geometry to export"),
+            ExportError::EmptySection => write!(f, "the section plane doesn't cut the geometry"),
+            ExportError::Cancelled => write!(f, "the export was cancelled"),
+            ExportError::Io(e) => write!(f, "couldn't write the export: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::EmptyScene | ExportError::EmptySection | ExportError::Cancelled => None,
+            ExportError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+/// Whether a primitive's color shows on the surface. Cuts show the color of
+/// what they cut unless they color their faces.
+fn shows_color(primitive: &geometry::BoxGeometry) -> bool {
+    !primitive.is_subtract || primitive.color_cut
+}
+
+/// Colors are linear in the scene, most formats expect them as displayed.
+fn display_color(color: Vec3) -> Srgba {
+    LinearRgba::rgb(color.x, color.y, color.z).into()
+}
+
+/// The nodes being exported as one scene.
+fn scoped_scene(nodes: &[geometry::SceneNode], options: &ExportOptions) -> sdf::Scene {
+    let nodes = options.scope.nodes(nodes, &options.selection);
+    let primitives = nodes.into_iter().flat_map(|node| node.primitives);
+
+    sdf::Scene::new(primitives.collect())
+}
+
+/// Mesh the distance field within `bounds`.
+fn mesh_field(
+    field: impl Fn(Vec3) -> f32 + Sync,
+    bounds: Aabb3d,
+    options: &ExportOptions,
+    progress: &ExportProgress,
+) -> TriangleMesh {
+    let size = Vec3::from(bounds.max - bounds.min);
+    let resolution = options.resolution.clamp(MIN_RESOLUTION, MAX_RESOLUTION);
+    let cell_size = size.max_element() / resolution as f32;
+    let field = &field;
+
+    // A cell of margin so the surface closes at the edges of the bounds
+    let region = bounds.grow(Vec3A::splat(cell_size));
+
+    if options.mesher == Mesher::Adaptive {
+        return octree::mesh(field, region, cell_size, options.max_error, progress);
+    }
+
+    // Sampling and meshing each fill about half of the stage
+    let Range { start, end } = progress.current_stage();
+    let middle = (start + end) * 0.5;
+
+    progress.stage(start..middle);
+    let grid = grid::SampledGrid::sample(field, region, cell_size, progress);
+    progress.stage(middle..end);
+
+    match options.mesher {
+        Mesher::Smooth => marching_cubes::mesh(&grid, progress),
+        Mesher::Sharp | Mesher::Adaptive => dual_contouring::mesh(&grid, field, progress),
+    }
+}
+
+/// Drop the checked mesh once the scene changes, whether or not the dialog
+/// is open, so a stale mesh is never saved.
+/// Exports already running carry on, they write the scene as it was.
+fn discard_stale_check(mut dialog: ResMut<ExportDialog>, bounds: Res<bounds::SceneBounds>) {
+    if !bounds.is_changed() {
+        return;
+    }
+
+    if dialog
+        .pending
+        .as_ref()
+        .is_some_and(|pending| pending.is_check)
+    {
+        dialog.cancel();
+    }
+    dialog.discard_check();
+}
+
+/// Pick up a finished background check or export.
+fn finish_pending(mut dialog: ResMut<ExportDialog>) {
+    if !dialog
+        .pending
+        .as_ref()
+        .is_some_and(|pending| pending.task.is_finished())
+    {
+        return;
+    }
+
+    let pending = dialog.pending.take().expect("finished job");
+
+    match block_on(pending.task) {
+        Ok(Finished::Checked(prepared, preview)) => {
+            dialog.prepared = Some(prepared);
+            dialog.preview = preview;
+        }
+        Ok(Finished::Written(message)) => dialog.status = Some(Ok(message)),
+        Err(ExportError::Cancelled) => {}
+        Err(e) => dialog.status = Some(Err(e.to_string())),
+    }
+}
+
+/// Draw the checked mesh as a wireframe over the scene.
+fn draw_preview(dialog: Res<ExportDialog>, mut gizmos: Gizmos) {
+    if !dialog.open || !dialog.show_preview {
+        return;
+    }
+
+    for [a, b] in &dialog.preview {
+        gizmos.line(*a, *b, ORANGE);
+    }
+}
+
+/// Show where the section will be cut while the dialog is set up for one.
+fn draw_section_plane(
+    dialog: Res<ExportDialog>,
+    bounds: Res<bounds::SceneBounds>,
+    mut gizmos: Gizmos,
+) {
+    let Some(bounds) = bounds
+        .0
+        .filter(|_| dialog.open && matches!(dialog.options.format, Format::Section(_)))
+    else {
+        return;
+    };
+
+    let plane = dialog.options.section;
+    let (min, max) = plane.extent(bounds);
+    let (x, y) = plane.drawing_axes();
+    let rotation = Quat::from_mat3(&Mat3::from_cols(x, y, plane.normal()));
+
+    gizmos.rect(
+        Isometry3d::new(plane.to_world((min + max) * 0.5), rotation),
+        max - min,
+        Color::from(AQUA),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn box_scene() -> sdf::Scene {
+        sdf::Scene::new(vec![geometry::BoxGeometry {
+            scale: Vec3::new(1.0, 0.5, 0.75),
+            ..geometry::BoxGeometry::new(Vec3::new(0.0, 1.0, 0.0), 0)
+        }])
+    }
+
+    pub(super) fn node(id: u32, primitive: geometry::BoxGeometry) -> geometry::SceneNode {
+        geometry::SceneNode {
+            id: primitive.id,
+            name: format!("Box {id}"),
+            primitives: vec![primitive],
+        }
+    }
+
+    #[test]
+    fn scene_changes_discard_the_check_while_closed() {
+        let mut world = World::new();
+        world.insert_resource(bounds::SceneBounds::default());
+        world.insert_resource(ExportDialog::default());
+        let system = world.register_system(discard_stale_check);
+        world.run_system(system).unwrap();
+
+        let checked = |world: &mut World| {
+            world.resource_mut::<ExportDialog>().prepared = Some(PreparedExport {
+                format: MeshFormat::StlBinary,
+                options: ExportOptions::default(),
+                meshes: Vec::new(),
+                report: MeshReport::default(),
+            });
+        };
+
+        checked(&mut world);
+        world.run_system(system).unwrap();
+        assert!(world.resource::<ExportDialog>().prepared.is_some());
+
+        world.resource_mut::<bounds::SceneBounds>().0 = None;
+        world.run_system(system).unwrap();
+        assert!(world.resource::<ExportDialog>().prepared.is_none());
+    }
+
+    #[test]
+    fn colors_follow_the_surface() {
+        let red = geometry::BoxGeometry {
+            color: [1.0, 0.0, 0.0],
+            scale: Vec3::ONE,
+            ..geometry::BoxGeometry::new(Vec3::ZERO, 0)
+        };
+        let blue = geometry::BoxGeometry {
+            color: [0.0, 0.0, 1.0],
+            blend: 0.2,
+            ..red.clone()
+        };
+        let scene = sdf::Scene::new(vec![
+            red,
+            geometry::BoxGeometry {
+                position: Vec3::X * 2.5,
+                ..blue
+            },
+        ]);
+
+        let mut mesh = TriangleMesh {
+            // Far side of the red box, then in the blend across the gap
+            positions: vec![Vec3::NEG_X, Vec3::new(1.25, 0.0, 0.0)],
+            ..default()
+        };
+        mesh.sample_colors(&scene);
+
+        assert_eq!(mesh.colors[0], Vec3::X);
+        assert!(mesh.colors[1].x > 0.1 && mesh.colors[1].z > 0.1);
+    }
+
+    #[test]
+    fn cut_faces_take_the_cut_color() {
+        let base = geometry::BoxGeometry {
+            color: [1.0, 0.0, 0.0],
+            scale: Vec3::ONE,
+            ..geometry::BoxGeometry::new(Vec3::ZERO, 0)
+        };
+        let cutter = |color_cut| geometry::BoxGeometry {
+            color: [0.0, 0.0, 1.0],
+            scale: Vec3::splat(0.5),
+            is_subtract: true,
+            color_cut,
+            ..geometry::BoxGeometry::new(Vec3::Y, 1)
+        };
+
+        // A triangle on the floor of the pocket
+        let mut mesh = TriangleMesh {
+            positions: vec![
+                Vec3::new(0.0, 0.5, 0.0),
+                Vec3::new(0.1, 0.5, 0.0),
+                Vec3::new(0.0, 0.5, 0.1),
+            ],
+            triangles: vec![[0, 1, 2]],
+            ..default()
+        };
+
+        mesh.sample_face_colors(&sdf::Scene::new(vec![base.clone(), cutter(false)]));
+        assert_eq!(mesh.face_colors, [Vec3::X]);
+
+        mesh.sample_face_colors(&sdf::Scene::new(vec![base, cutter(true)]));
+        assert_eq!(mesh.face_colors, [Vec3::Z]);
+    }
+
+    #[test]
+    fn selection_can_keep_its_cuts() {
+        let cut = geometry::BoxGeometry {
+            is_subtract: true,
+            ..geometry::BoxGeometry::new(Vec3::ZERO, 2)
+        };
+        let nodes = [
+            node(1, geometry::BoxGeometry::new(Vec3::ZERO, 1)),
+            node(2, cut),
+            node(3, geometry::BoxGeometry::new(Vec3::X * 10.0, 3)),
+        ];
+        let selection = [node_id::NodeId::new(1)];
+
+        let alone = Scope::Selection.nodes(&nodes, &selection);
+        assert_eq!(alone.len(), 1);
+        assert_eq!(alone[0].id, selection[0]);
+
+        let cut = Scope::SelectionWithCuts.nodes(&nodes, &selection);
+        let counts: Vec<usize> = cut.iter().map(|n| n.primitives.len()).collect();
+        assert_eq!(counts, [1, 1, 0]);
+    }
+}
@@ -0,0 +1,295 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use bevy::prelude::*;
+
+use super::dual_contouring::{Crossing, Qef};
+use super::{Decimation, TriangleMesh};
+
+/// Folding a triangle further than this, as the cosine between its normals
+/// before and after, rejects the collapse.
+const MIN_NORMAL_DOT: f32 = 0.2;
+
+/// Collapse costs are compared in steps of the allowed error over this.
+/// Within a step, shorter edges go first, so flat areas are reduced evenly
+/// rather than fanning out around one vertex.
+const COST_STEPS: f32 = 1024.0;
+
+/// Collapse edges cheapest first, measured by the squared distance the
+/// merged vertex sits from the planes of the triangles it replaces, until
+/// the mesh is down to the target or the next collapse would move the
+/// surface too far. Collapses that would tear the surface or flip a
+/// triangle are skipped. Only positions and triangles are kept, sampled
+/// attributes need sampling again.
+pub fn decimate(mesh: &TriangleMesh, decimation: Decimation) -> TriangleMesh {
+    let max_cost = (decimation.max_error * decimation.max_error).max(f32::MIN_POSITIVE);
+    let mut decimator = Decimator::new(mesh, max_cost);
+
+    while decimator.triangle_count > decimation.target_triangles.max(4) {
+        let Some(collapse) = decimator.heap.pop() else {
+            break;
+        };
+
+        // Everything left costs at least as much
+        if collapse.step > COST_STEPS as u32 {
+            break;
+        }
+
+        if collapse.cost <= max_cost
+            && decimator.is_current(&collapse)
+            && decimator.can_collapse(&collapse)
+        {
+            decimator.collapse(&collapse);
+        }
+    }
+
+    decimator.into_mesh()
+}
+
+/// Merging `b` into `a` at `position`, valid while neither vertex changes.
+struct Collapse {
+    cost: f32,
+    step: u32,
+    length: f32,
+    a: u32,
+    b: u32,
+    position: Vec3,
+    stamps: (u32, u32),
+}
+
+// Ordered so the heap pops the cheapest first
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .step
+            .cmp(&self.step)
+            .then(other.length.total_cmp(&self.length))
+    }
+}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+struct Decimator {
+    positions: Vec<Vec3>,
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    triangle_count: usize,
+    /// Triangles around each vertex, including some that have since been
+    /// removed.
+    around: Vec<Vec<u32>>,
+    quadrics: Vec<Qef>,
+    /// Bumped whenever a vertex moves or goes, outdating its collapses.
+    stamps: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+    max_cost: f32,
+}
+
+impl Decimator {
+    fn new(mesh: &TriangleMesh, max_cost: f32) -> Self {
+        let vertex_count = mesh.positions.len();
+        let mut around = vec![Vec::new(); vertex_count];
+        let mut quadrics = vec![Qef::default(); vertex_count];
+
+        for (t, triangle) in mesh.triangles.iter().enumerate() {
+            let [a, b, c] = triangle.map(|i| mesh.positions[i as usize]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+
+            for &i in triangle {
+                around[i as usize].push(t as u32);
+                quadrics[i as usize].add(Crossing { point: a, normal });
+            }
+        }
+
+        let mut decimator = Self {
+            positions: mesh.positions.clone(),
+            triangles: mesh.triangles.clone(),
+            alive: vec![true; mesh.triangles.len()],
+            triangle_count: mesh.triangles.len(),
+            around,
+            quadrics,
+            stamps: vec![0; vertex_count],
+            heap: BinaryHeap::new(),
+            max_cost,
+        };
+
+        let mut edges: Vec<(u32, u32)> = mesh
+            .triangles
+            .iter()
+            .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+
+        for (a, b) in edges {
+            decimator.push(a, b);
+        }
+
+        decimator
+    }
+
+    /// Queue the collapse of the edge between `a` and `b`, at whichever of
+    /// the quadric's best fit, the ends or the middle fits best.
+    fn push(&mut self, a: u32, b: u32) {
+        let mut quadric = self.quadrics[a as usize];
+        quadric.merge(&self.quadrics[b as usize]);
+
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+
+        let (cost, position) = [
+            quadric.place(pa.min(pb), pa.max(pb)),
+            pa,
+            pb,
+            (pa + pb) * 0.5,
+        ]
+        .into_iter()
+        .map(|p| (quadric.error(p), p))
+        .min_by(|x, y| x.0.total_cmp(&y.0))
+        .expect("candidates");
+
+        self.heap.push(Collapse {
+            cost,
+            step: (cost / self.max_cost * COST_STEPS).min(u32::MAX as f32) as u32,
+            length: pa.distance_squared(pb),
+            a,
+            b,
+            position,
+            stamps: (self.stamps[a as usize], self.stamps[b as usize]),
+        });
+    }
+
+    fn is_current(&self, collapse: &Collapse) -> bool {
+        collapse.stamps
+            == (
+                self.stamps[collapse.a as usize],
+                self.stamps[collapse.b as usize],
+            )
+    }
+
+    fn live_around(&self, v: u32) -> impl Iterator<Item = u32> + '_ {
+        self.around[v as usize]
+            .iter()
+            .copied()
+            .filter(|t| self.alive[*t as usize])
+    }
+
+    fn neighbors(&self, v: u32) -> Vec<u32> {
+        let mut neighbors: Vec<u32> = self
+            .live_around(v)
+            .flat_map(|t| self.triangles[t as usize])
+            .filter(|i| *i != v)
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    fn can_collapse(&self, collapse: &Collapse) -> bool {
+        let (a, b) = (collapse.a, collapse.b);
+
+        // Vertices joined to both ends must be the far corners of the
+        // triangles on the edge, or the collapse pinches the surface
+        let a_neighbors = self.neighbors(a);
+        let shared_vertices = self
+            .neighbors(b)
+            .iter()
+            .filter(|v| a_neighbors.binary_search(v).is_ok())
+            .count();
+        let shared_triangles = self
+            .live_around(a)
+            .filter(|t| self.triangles[*t as usize].contains(&b))
+            .count();
+
+        if shared_triangles == 0 || shared_triangles > 2 || shared_vertices != shared_triangles {
+            return false;
+        }
+
+        // No remaining triangle may fold over or collapse to a line
+        self.live_around(a).chain(self.live_around(b)).all(|t| {
+            let triangle = self.triangles[t as usize];
+            if triangle.contains(&a) && triangle.contains(&b) {
+                return true;
+            }
+
+            let before = triangle.map(|i| self.positions[i as usize]);
+            let after = triangle.map(|i| {
+                if i == a || i == b {
+                    collapse.position
+                } else {
+                    self.positions[i as usize]
+                }
+            });
+
+            let normal = |[p, q, r]: [Vec3; 3]| (q - p).cross(r - p).normalize_or_zero();
+            normal(before).dot(normal(after)) > MIN_NORMAL_DOT
+        })
+    }
+
+    fn collapse(&mut self, collapse: &Collapse) {
+        let (a, b) = (collapse.a, collapse.b);
+
+        for t in std::mem::take(&mut self.around[b as usize]) {
+            if !self.alive[t as usize] {
+                continue;
+            }
+
+            let triangle = &mut self.triangles[t as usize];
+            if triangle.contains(&a) {
+                self.alive[t as usize] = false;
+                self.triangle_count -= 1;
+            } else {
+                for i in triangle.iter_mut().filter(|i| **i == b) {
+                    *i = a;
+                }
+                self.around[a as usize].push(t);
+            }
+        }
+
+        let merged = self.quadrics[b as usize];
+        self.quadrics[a as usize].merge(&merged);
+        self.positions[a as usize] = collapse.position;
+        self.stamps[a as usize] += 1;
+        self.stamps[b as usize] += 1;
+
+        let alive = &self.alive;
+        self.around[a as usize].retain(|t| alive[*t as usize]);
+
+        for neighbor in self.neighbors(a) {
+            self.push(a, neighbor);
+        }
+    }
+
+    fn into_mesh(self) -> TriangleMesh {
+        let mut mesh = TriangleMesh::default();
+        let mut remap = vec![u32::MAX; self.positions.len()];
+
+        for (triangle, alive) in self.triangles.iter().zip(&self.alive) {
+            if !alive {
+                continue;
+            }
+
+            let triangle = triangle.map(|i| {
+                if remap[i as usize] == u32::MAX {
+                    remap[i as usize] = mesh.positions.len() as u32;
+                    mesh.positions.push(self.positions[i as usize]);
+                }
+                remap[i as usize]
+            });
+            mesh.triangles.push(triangle);
+        }
+
+        mesh
+    }
+}
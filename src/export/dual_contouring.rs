@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use bevy::math::{DMat3, DVec3};
+use bevy::prelude::*;
+
+use super::{ExportProgress, TriangleMesh, grid::SampledGrid};
+
+/// Pull towards the average crossing point. Keeps vertices put on flat and
+/// gently curved surfaces, where the planes don't meet at a single point.
+const REGULARIZATION: f64 = 0.01;
+
+/// Refinement steps used to find where an edge crosses the surface.
+const CROSSING_STEPS: u32 = 6;
+
+/// Where the surface crosses a cell edge, and its normal there.
+#[derive(Debug, Clone, Copy)]
+pub struct Crossing {
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+/// The squared distances from a point to the tangent planes at a set of
+/// crossings, as a quadratic that can be merged and minimized. Kept in
+/// double precision since the error is a difference of large terms.
+#[derive(Debug, Clone, Copy)]
+pub struct Qef {
+    ata: DMat3,
+    atb: DVec3,
+    btb: f64,
+    point_sum: DVec3,
+    count: u32,
+}
+
+// Not derived, the default matrix is the identity
+impl Default for Qef {
+    fn default() -> Self {
+        Self {
+            ata: DMat3::ZERO,
+            atb: DVec3::ZERO,
+            btb: 0.0,
+            point_sum: DVec3::ZERO,
+            count: 0,
+        }
+    }
+}
+
+impl Qef {
+    pub fn add(&mut self, crossing: Crossing) {
+        let n = crossing.normal.as_dvec3();
+        let p = crossing.point.as_dvec3();
+        let b = n.dot(p);
+
+        self.ata += DMat3::from_cols(n * n.x, n * n.y, n * n.z);
+        self.atb += n * b;
+        self.btb += b * b;
+        self.point_sum += p;
+        self.count += 1;
+    }
+
+    pub fn merge(&mut self, other: &Qef) {
+        self.ata += other.ata;
+        self.atb += other.atb;
+        self.btb += other.btb;
+        self.point_sum += other.point_sum;
+        self.count += other.count;
+    }
+
+    /// Sum of squared distances from `x` to the planes.
+    pub fn error(&self, x: Vec3) -> f32 {
+        let x = x.as_dvec3();
+        (x.dot(self.ata * x) - 2.0 * x.dot(self.atb) + self.btb).max(0.0) as f32
+    }
+
+    /// The point closest to all the planes, solved relative to their average
+    /// so the regularization pulls towards it. Falls back to the average
+    /// when nearly parallel planes meet outside of `min`..`max` grown by
+    /// half its size.
+    pub fn place(&self, min: Vec3, max: Vec3) -> Vec3 {
+        let mass_point = self.point_sum / self.count.max(1) as f64;
+
+        let regularized = self.ata + DMat3::from_diagonal(DVec3::splat(REGULARIZATION));
+        let vertex = mass_point + regularized.inverse() * (self.atb - self.ata * mass_point);
+        let vertex = vertex.as_vec3();
+
+        let slack = (max - min) * 0.5;
+        if vertex.cmpge(min - slack).all() && vertex.cmple(max + slack).all() {
+            vertex
+        } else {
+            mass_point.as_vec3()
+        }
+    }
+}
+
+/// Find where the surface crosses between a point inside and a point
+/// outside. Interpolation is only exact for planes, so the bracket is
+/// narrowed against the field.
+pub fn surface_crossing(
+    field: &impl Fn(Vec3) -> f32,
+    mut inside: Vec3,
+    mut outside: Vec3,
+    h: f32,
+) -> Crossing {
+    let (mut d_in, mut d_out) = (field(inside), field(outside));
+    let mut point = inside;
+
+    for _ in 0..CROSSING_STEPS {
+        let t = (d_in / (d_in - d_out)).clamp(0.0, 1.0);
+        point = inside.lerp(outside, t);
+
+        let d = field(point);
+        if d.abs() < h * 1e-3 {
+            break;
+        }
+
+        if d < 0.0 {
+            (inside, d_in) = (point, d);
+        } else {
+            (outside, d_out) = (point, d);
+        }
+    }
+
+    Crossing {
+        point,
+        normal: gradient(field, point, h),
+    }
+}
+
+/// Central differences from the corners of a tetrahedron, four samples
+/// rather than six.
+fn gradient(field: &impl Fn(Vec3) -> f32, p: Vec3, h: f32) -> Vec3 {
+    [
+        Vec3::new(1.0, -1.0, -1.0),
+        Vec3::new(-1.0, -1.0, 1.0),
+        Vec3::new(-1.0, 1.0, -1.0),
+        Vec3::new(1.0, 1.0, 1.0),
+    ]
+    .into_iter()
+    .map(|k| k * field(p + k * h))
+    .sum::<Vec3>()
+    .normalize_or_zero()
+}
+
+/// Extract the zero surface of `field`, sampled in `grid`, as a closed mesh.
+/// Each cell the surface passes through gets one vertex, placed where the
+/// tangent planes at its edge crossings meet, so sharp edges and corners
+/// are kept rather than cut off as with marching cubes.
+pub fn mesh(
+    grid: &SampledGrid,
+    field: impl Fn(Vec3) -> f32,
+    progress: &ExportProgress,
+) -> TriangleMesh {
+    let crossings = find_crossings(grid, &field, progress);
+
+    let mut mesh = TriangleMesh::default();
+    let mut cell_vertices: HashMap<UVec3, u32> = HashMap::new();
+
+    let mut cell_vertex = |cell: UVec3| {
+        *cell_vertices.entry(cell).or_insert_with(|| {
+            let mut qef = Qef::default();
+            for edge in cell_edges(cell) {
+                if let Some(crossing) = crossings.get(&edge) {
+                    qef.add(*crossing);
+                }
+            }
+
+            mesh.positions
+                .push(qef.place(grid.position(cell), grid.position(cell + 1)));
+            mesh.positions.len() as u32 - 1
+        })
+    };
+
+    // Visit edges in grid order so the output doesn't vary between runs
+    let mut edges: Vec<_> = crossings.keys().copied().collect();
+    edges.sort_by_key(|&(start, axis)| (grid.index(start), axis));
+
+    let mut triangles = Vec::new();
+
+    for (start, axis) in edges {
+        let (u, v) = (UVec3::AXES[(axis + 1) % 3], UVec3::AXES[(axis + 2) % 3]);
+
+        // Edges on the faces of the grid have no cells on one side, the
+        // margin around the sampled region keeps the surface away from them
+        if start.dot(u) == 0 || start.dot(v) == 0 {
+            continue;
+        }
+
+        let quad = [start, start - u, start - u - v, start - v].map(&mut cell_vertex);
+
+        // Face away from the inside end of the edge
+        let quad = if grid.value(start) < 0.0 {
+            quad
+        } else {
+            [quad[0], quad[3], quad[2], quad[1]]
+        };
+
+        triangles.push([quad[0], quad[1], quad[2]]);
+        triangles.push([quad[0], quad[2], quad[3]]);
+    }
+
+    mesh.triangles = triangles;
+    mesh
+}
+
+/// Every grid edge the surface crosses, keyed by its lower end and axis.
+/// Finding where is most of the work, so each slab advances `progress`.
+fn find_crossings(
+    grid: &SampledGrid,
+    field: &impl Fn(Vec3) -> f32,
+    progress: &ExportProgress,
+) -> HashMap<(UVec3, usize), Crossing> {
+    let mut crossings = HashMap::new();
+
+    for z in 0..grid.dims.z {
+        if progress.is_cancelled() {
+            break;
+        }
+        progress.advance(z as usize, grid.dims.z as usize);
+
+        for y in 0..grid.dims.y {
+            for x in 0..grid.dims.x {
+                let start = UVec3::new(x, y, z);
+                let inside = grid.value(start) < 0.0;
+
+                for (axis, step) in UVec3::AXES.into_iter().enumerate() {
+                    let end = start + step;
+
+                    if end.cmplt(grid.dims).all() && inside != (grid.value(end) < 0.0) {
+                        let (a, b) = (grid.position(start), grid.position(end));
+                        let (inside, outside) = if inside { (a, b) } else { (b, a) };
+
+                        let crossing =
+                            surface_crossing(field, inside, outside, grid.cell_size * 0.01);
+                        crossings.insert((start, axis), crossing);
+                    }
+                }
+            }
+        }
+    }
+
+    crossings
+}
+
+/// The twelve edges of a cell, as keys into the crossings.
+fn cell_edges(cell: UVec3) -> impl Iterator<Item = (UVec3, usize)> {
+    (0..3).flat_map(move |axis| {
+        let (u, v) = (UVec3::AXES[(axis + 1) % 3], UVec3::AXES[(axis + 2) % 3]);
+
+        [UVec3::ZERO, u, v, u + v].map(|offset| (cell + offset, axis))
+    })
+}
@@ -0,0 +1,186 @@
+use std::io::{self, Write};
+
+use bevy::prelude::*;
+use serde_json::{Value, json};
+
+use super::TriangleMesh;
+
+const MAGIC: &[u8; 4] = b"glTF";
+const VERSION: u32 = 2;
+const JSON_CHUNK: &[u8; 4] = b"JSON";
+const BIN_CHUNK: &[u8; 4] = b"BIN\0";
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const TRIANGLES: u32 = 4;
+
+/// Write glTF 2.0 binary with a node and mesh for each of `meshes`, named
+/// after it. Positions stay Y up, as glTF expects, and vertex colors are
+/// written when the mesh has them. Normals are left out so viewers shade
+/// facets flat, which keeps sharp edges sharp.
+pub fn write_glb(meshes: &[(String, TriangleMesh)], writer: &mut impl Write) -> io::Result<()> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut gltf_meshes = Vec::new();
+    let mut nodes = Vec::new();
+
+    // Each attribute gets its own view, the buffer stays 4 byte aligned
+    // since every component is
+    let mut add_view = |buffer: &mut Vec<u8>, bytes: Vec<u8>, target: u32| {
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        buffer.extend(bytes);
+        buffer_views.len() - 1
+    };
+
+    for (name, mesh) in meshes {
+        if mesh.triangles.is_empty() {
+            continue;
+        }
+
+        let (min, max) = mesh
+            .positions
+            .iter()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), p| {
+                (min.min(*p), max.max(*p))
+            });
+
+        let view = add_view(&mut buffer, vec3_bytes(&mesh.positions), ARRAY_BUFFER);
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": mesh.positions.len(),
+            "type": "VEC3",
+            "min": min.to_array(),
+            "max": max.to_array(),
+        }));
+        let mut attributes = json!({ "POSITION": accessors.len() - 1 });
+
+        if mesh.colors.len() == mesh.positions.len() {
+            let view = add_view(&mut buffer, vec3_bytes(&mesh.colors), ARRAY_BUFFER);
+            accessors.push(json!({
+                "bufferView": view,
+                "componentType": FLOAT,
+                "count": mesh.colors.len(),
+                "type": "VEC3",
+            }));
+            attributes["COLOR_0"] = json!(accessors.len() - 1);
+        }
+
+        let indices: Vec<u8> = mesh
+            .triangles
+            .iter()
+            .flatten()
+            .flat_map(|i| i.to_le_bytes())
+            .collect();
+        let view = add_view(&mut buffer, indices, ELEMENT_ARRAY_BUFFER);
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": mesh.triangles.len() * 3,
+            "type": "SCALAR",
+        }));
+
+        gltf_meshes.push(json!({
+            "name": name,
+            "primitives": [{
+                "attributes": attributes,
+                "indices": accessors.len() - 1,
+                "mode": TRIANGLES,
+            }],
+        }));
+        nodes.push(json!({ "name": name, "mesh": gltf_meshes.len() - 1 }));
+    }
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "raystacean" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": gltf_meshes,
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+        "buffers": [{ "byteLength": buffer.len() }],
+    });
+
+    write_chunks(&document, buffer, writer)
+}
+
+fn write_chunks(document: &Value, mut buffer: Vec<u8>, writer: &mut impl Write) -> io::Result<()> {
+    let mut json = serde_json::to_vec(document)?;
+
+    // Chunks are 4 byte aligned, JSON padded with spaces and binary with zeros
+    json.resize(json.len().next_multiple_of(4), b' ');
+    buffer.resize(buffer.len().next_multiple_of(4), 0);
+
+    let length = 12 + 8 + json.len() + 8 + buffer.len();
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(length as u32).to_le_bytes())?;
+
+    for (kind, data) in [(JSON_CHUNK, json), (BIN_CHUNK, buffer)] {
+        writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        writer.write_all(kind)?;
+        writer.write_all(&data)?;
+    }
+
+    Ok(())
+}
+
+fn vec3_bytes(values: &[Vec3]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|v| v.to_array())
+        .flat_map(f32::to_le_bytes)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn glb_layout() {
+        let mesh = TriangleMesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            triangles: vec![[0, 1, 2]],
+            colors: vec![Vec3::X; 3],
+            ..default()
+        };
+
+        let mut bytes = Vec::new();
+        write_glb(&[("Box 1".to_string(), mesh)], &mut bytes).unwrap();
+
+        assert_eq!(&bytes[0..4], MAGIC);
+        assert_eq!(u32_at(&bytes, 8) as usize, bytes.len());
+
+        let json_length = u32_at(&bytes, 12) as usize;
+        assert_eq!(&bytes[16..20], JSON_CHUNK);
+        assert_eq!(json_length % 4, 0);
+
+        let document: Value = serde_json::from_slice(&bytes[20..20 + json_length]).unwrap();
+        let bin = 20 + json_length;
+        assert_eq!(&bytes[bin + 4..bin + 8], BIN_CHUNK);
+
+        // Positions and colors, 36 bytes each, then 12 bytes of indices
+        assert_eq!(document["buffers"][0]["byteLength"], 84);
+        assert_eq!(document["nodes"][0]["name"], "Box 1");
+
+        let attributes = &document["meshes"][0]["primitives"][0]["attributes"];
+        let colors = &document["accessors"][attributes["COLOR_0"].as_u64().unwrap() as usize];
+        assert_eq!(colors["count"], 3);
+        assert_eq!(document["accessors"][0]["max"], json!([1.0, 1.0, 0.0]));
+    }
+}
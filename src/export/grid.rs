@@ -0,0 +1,103 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bevy::math::bounding::Aabb3d;
+use bevy::prelude::*;
+
+use super::ExportProgress;
+
+/// A distance field sampled at the corners of a regular grid of cubic cells.
+#[derive(Debug, Clone)]
+pub struct SampledGrid {
+    pub origin: Vec3,
+    pub cell_size: f32,
+    /// Number of samples along each axis, one more than the number of cells.
+    pub dims: UVec3,
+    /// Samples ordered x fastest, then y, then z.
+    pub values: Vec<f32>,
+}
+
+impl SampledGrid {
+    /// Sample `field` over `region` with cells of `cell_size`, rounding the
+    /// region up to a whole number of cells.
+    pub fn sample(
+        field: impl Fn(Vec3) -> f32 + Sync,
+        region: Aabb3d,
+        cell_size: f32,
+        progress: &ExportProgress,
+    ) -> Self {
+        let cells = (Vec3::from(region.max - region.min) / cell_size)
+            .ceil()
+            .as_uvec3()
+            .max(UVec3::ONE);
+
+        Self::sample_points(field, region.min.into(), cell_size, cells + 1, progress)
+    }
+
+    /// Sample `field` at `dims` points spaced `cell_size` apart from
+    /// `origin`. Slices are shared out between all available threads, and
+    /// advance `progress` as they're finished. Once it's cancelled, the
+    /// slices left stay unsampled.
+    pub fn sample_points(
+        field: impl Fn(Vec3) -> f32 + Sync,
+        origin: Vec3,
+        cell_size: f32,
+        dims: UVec3,
+        progress: &ExportProgress,
+    ) -> Self {
+        let mut values = vec![0.0; (dims.x * dims.y * dims.z) as usize];
+        let slice_len = (dims.x * dims.y) as usize;
+
+        let slices = Mutex::new(values.chunks_mut(slice_len).enumerate());
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let sampled = AtomicUsize::new(0);
+
+        std::thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    loop {
+                        let Some((z, slice)) =
+                            slices.lock().expect("sampling thread panicked").next()
+                        else {
+                            break;
+                        };
+                        if progress.is_cancelled() {
+                            break;
+                        }
+
+                        for (i, value) in slice.iter_mut().enumerate() {
+                            let index = UVec3::new(i as u32 % dims.x, i as u32 / dims.x, z as u32);
+                            *value = field(origin + index.as_vec3() * cell_size);
+                        }
+
+                        let done = sampled.fetch_add(1, Ordering::Relaxed) + 1;
+                        progress.advance(done, dims.z as usize);
+                    }
+                });
+            }
+        });
+
+        Self {
+            origin,
+            cell_size,
+            dims,
+            values,
+        }
+    }
+
+    pub fn cells(&self) -> UVec3 {
+        self.dims - 1
+    }
+
+    pub fn index(&self, point: UVec3) -> usize {
+        (point.x + self.dims.x * (point.y + self.dims.y * point.z)) as usize
+    }
+
+    pub fn value(&self, point: UVec3) -> f32 {
+        self.values[self.index(point)]
+    }
+
+    pub fn position(&self, point: UVec3) -> Vec3 {
+        self.origin + point.as_vec3() * self.cell_size
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::{self, Seek, Write};
+
+use bevy::math::bounding::Aabb3d;
+use bevy::prelude::*;
+use serde_json::json;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use super::{ExportProgress, LayerSlicing, grid::SampledGrid};
+
+const INSIDE: u8 = 255;
+const OUTSIDE: u8 = 0;
+
+/// Size of the stack written by [`write_layer_stack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackSize {
+    pub width: u32,
+    pub height: u32,
+    pub layers: u32,
+}
+
+/// Slice `bounds` from the bottom up and write each layer as a black and
+/// white PNG, white inside, into a zip with a `metadata.json` describing
+/// the stack. Each layer is sampled through its middle, each pixel at its
+/// center. Images look down on the scene, x along X and rows along Z.
+/// Progress is reported after each layer, and once cancelled the stack is
+/// cut short.
+pub fn write_layer_stack(
+    field: impl Fn(Vec3) -> f32 + Sync,
+    bounds: Aabb3d,
+    slicing: LayerSlicing,
+    progress: &ExportProgress,
+    writer: &mut (impl Write + Seek),
+) -> io::Result<StackSize> {
+    let (min, size) = (Vec3::from(bounds.min), Vec3::from(bounds.max - bounds.min));
+    let size = StackSize {
+        width: (size.x / slicing.pixel_size).ceil().max(1.0) as u32,
+        height: (size.z / slicing.pixel_size).ceil().max(1.0) as u32,
+        layers: (size.y / slicing.layer_height).ceil().max(1.0) as u32,
+    };
+
+    let mut zip = ZipWriter::new(writer);
+    // Images are compressed already
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let mut names = Vec::new();
+
+    for layer in 0..size.layers {
+        if progress.is_cancelled() {
+            break;
+        }
+        let layers = size.layers as f32;
+        progress.stage(layer as f32 / layers..(layer + 1) as f32 / layers);
+
+        let origin = min
+            + Vec3::new(
+                slicing.pixel_size * 0.5,
+                slicing.layer_height * (layer as f32 + 0.5),
+                slicing.pixel_size * 0.5,
+            );
+
+        // A single row of samples in y, so x then z
+        let grid = SampledGrid::sample_points(
+            &field,
+            origin,
+            slicing.pixel_size,
+            UVec3::new(size.width, 1, size.height),
+            progress,
+        );
+        let pixels: Vec<u8> = grid
+            .values
+            .iter()
+            .map(|d| if *d < 0.0 { INSIDE } else { OUTSIDE })
+            .collect();
+
+        let name = format!("layer_{layer:05}.png");
+        zip.start_file(name.as_str(), stored)?;
+        encode_png(&pixels, size.width, size.height, &mut zip)?;
+        names.push(name);
+    }
+
+    let metadata = json!({
+        "width": size.width,
+        "height": size.height,
+        "layer_count": size.layers,
+        "pixel_size": slicing.pixel_size,
+        "layer_height": slicing.layer_height,
+        "origin": min.to_array(),
+        "image_x": "+X",
+        "image_rows": "+Z",
+        "inside": "white",
+        "layers": names,
+    });
+
+    zip.start_file("metadata.json", SimpleFileOptions::default())?;
+    serde_json::to_writer_pretty(&mut zip, &metadata)?;
+
+    zip.finish()?;
+    Ok(size)
+}
+
+fn encode_png(pixels: &[u8], width: u32, height: u32, writer: impl Write) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut png = encoder.write_header()?;
+    png.write_image_data(pixels)?;
+    png.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn layers_slice_up_through_the_scene() {
+        // A cone standing on the origin, narrowing to a point at y = 1
+        let cone = |p: Vec3| Vec2::new(p.x, p.z).length() - (1.0 - p.y);
+        let bounds = Aabb3d {
+            min: Vec3A::new(-1.0, 0.0, -1.0),
+            max: Vec3A::new(1.0, 1.0, 1.0),
+        };
+        let slicing = LayerSlicing {
+            layer_height: 0.25,
+            pixel_size: 0.1,
+        };
+
+        let mut bytes = Cursor::new(Vec::new());
+        let progress = ExportProgress::default();
+        let size = write_layer_stack(cone, bounds, slicing, &progress, &mut bytes).unwrap();
+        assert_eq!(
+            size,
+            StackSize {
+                width: 20,
+                height: 20,
+                layers: 4
+            }
+        );
+
+        let mut archive = zip::ZipArchive::new(bytes).unwrap();
+
+        let mut metadata = String::new();
+        archive
+            .by_name("metadata.json")
+            .unwrap()
+            .read_to_string(&mut metadata)
+            .unwrap();
+        let metadata: Value = serde_json::from_str(&metadata).unwrap();
+        assert_eq!(metadata["layer_count"], 4);
+        assert_eq!(metadata["layers"][3], "layer_00003.png");
+
+        // Each layer up covers less
+        let mut lit = Vec::new();
+        for layer in 0..4 {
+            let file = archive.by_name(&format!("layer_{layer:05}.png")).unwrap();
+            let mut reader = png::Decoder::new(file).read_info().unwrap();
+            let mut pixels = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut pixels).unwrap();
+
+            assert!(pixels.iter().all(|p| *p == INSIDE || *p == OUTSIDE));
+            lit.push(pixels.iter().filter(|p| **p == INSIDE).count());
+        }
+
+        assert!(lit.windows(2).all(|pair| pair[0] > pair[1]), "{lit:?}");
+        assert!(lit[3] > 0);
+    }
+}
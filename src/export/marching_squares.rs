@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+/// A grid edge, as its lower end and 0 along x or 1 along y.
+type EdgeKey = (UVec2, u8);
+
+/// Trace the zero contours of `field` between `min` and `max`, sampled
+/// every `cell_size`. Contours are closed loops with the inside on their
+/// left, so outlines run counter-clockwise and holes clockwise. Anything
+/// touching the edge of the region is cut open there, callers leave a
+/// margin.
+pub fn contours(
+    field: impl Fn(Vec2) -> f32,
+    min: Vec2,
+    max: Vec2,
+    cell_size: f32,
+) -> Vec<Vec<Vec2>> {
+    let cells = ((max - min) / cell_size).ceil().as_uvec2().max(UVec2::ONE);
+    let dims = cells + 1;
+
+    let position = |point: UVec2| min + point.as_vec2() * cell_size;
+    let values: Vec<f32> = (0..dims.y)
+        .flat_map(|y| (0..dims.x).map(move |x| UVec2::new(x, y)))
+        .map(|point| field(position(point)))
+        .collect();
+    let value = |point: UVec2| values[(point.x + point.y * dims.x) as usize];
+
+    // Where each crossed edge meets the contour, found the same way from
+    // either cell so the loops join up
+    let crossing = |(start, axis): EdgeKey| {
+        let end = start + if axis == 0 { UVec2::X } else { UVec2::Y };
+        let (a, b) = (value(start), value(end));
+        position(start).lerp(position(end), a / (a - b))
+    };
+
+    let mut next: HashMap<EdgeKey, EdgeKey> = HashMap::new();
+
+    for y in 0..cells.y {
+        for x in 0..cells.x {
+            let cell = UVec2::new(x, y);
+
+            // Corners and edges counter-clockwise from the lower left
+            let corners = [cell, cell + UVec2::X, cell + 1, cell + UVec2::Y];
+            let edges: [EdgeKey; 4] = [
+                (cell, 0),
+                (cell + UVec2::X, 1),
+                (cell + UVec2::Y, 0),
+                (cell, 1),
+            ];
+            let inside = corners.map(|corner| value(corner) < 0.0);
+
+            let leaving = |i: usize| inside[i] && !inside[(i + 1) % 4];
+            let entering = |i: usize| !inside[i] && inside[(i + 1) % 4];
+
+            // Saddles join the inside corners when the middle is inside too
+            let crossings = (0..4).filter(|i| leaving(*i) || entering(*i)).count();
+            let center_inside = crossings == 4 && field(position(cell) + cell_size * 0.5) < 0.0;
+
+            // Each contour leaves the inside and turns to meet the next
+            // edge where it enters, keeping the inside on its left
+            for i in (0..4).filter(|i| leaving(*i)) {
+                let turn = |k: usize| {
+                    if center_inside || crossings == 2 {
+                        (i + k) % 4
+                    } else {
+                        (i + 4 - k) % 4
+                    }
+                };
+
+                if let Some(j) = (1..4).map(turn).find(|j| entering(*j)) {
+                    next.insert(edges[i], edges[j]);
+                }
+            }
+        }
+    }
+
+    // Follow the links around each loop, starting in grid order so the
+    // output doesn't vary between runs
+    let mut starts: Vec<EdgeKey> = next.keys().copied().collect();
+    starts.sort_by_key(|(point, axis)| (point.y, point.x, *axis));
+
+    let mut loops = Vec::new();
+
+    for start in starts {
+        let Some(mut edge) = next.remove(&start) else {
+            continue;
+        };
+
+        let mut points = vec![crossing(start)];
+        while edge != start {
+            points.push(crossing(edge));
+            match next.remove(&edge) {
+                Some(following) => edge = following,
+                None => break,
+            }
+        }
+
+        loops.push(points);
+    }
+
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_area(points: &[Vec2]) -> f32 {
+        points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| a.perp_dot(*b))
+            .sum::<f32>()
+            * 0.5
+    }
+
+    #[test]
+    fn ring_has_an_outline_and_a_hole() {
+        let ring = |p: Vec2| (p.length() - 2.0).abs() - 0.5;
+        let loops = contours(ring, Vec2::splat(-3.0), Vec2::splat(3.0), 0.05);
+
+        assert_eq!(loops.len(), 2);
+
+        let mut areas: Vec<f32> = loops.iter().map(|l| signed_area(l)).collect();
+        areas.sort_by(f32::total_cmp);
+
+        let circle = |r: f32| std::f32::consts::PI * r * r;
+        assert!((areas[0] + circle(1.5)).abs() < 0.05, "hole {}", areas[0]);
+        assert!(
+            (areas[1] - circle(2.5)).abs() < 0.05,
+            "outline {}",
+            areas[1]
+        );
+    }
+
+    #[test]
+    fn saddles_follow_the_center() {
+        // Two discs meeting in a cell around the origin, whose corners
+        // alternate, joined in the middle or not depending on their size
+        let discs =
+            |radius: f32| move |p: Vec2| (p + 0.5).length().min((p - 0.5).length()) - radius;
+        let traced = |radius| contours(discs(radius), Vec2::splat(-2.25), Vec2::splat(2.25), 0.5);
+
+        let joined = traced(0.75);
+        assert_eq!(joined.len(), 1);
+        assert!(signed_area(&joined[0]) > 0.0);
+
+        let apart = traced(0.65);
+        assert_eq!(apart.len(), 2);
+        assert!(apart.iter().all(|l| signed_area(l) > 0.0));
+    }
+}
@@ -0,0 +1,684 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+
+use super::{
+    ExportError, ExportOptions, ExportProgress, TriangleMesh, Unit, decimate, gltf, mesh_field,
+    obj, ply, smooth, stl, threemf, validate,
+};
+use crate::{bounds, geometry, sdf};
+
+/// Formats holding a triangle mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFormat {
+    StlBinary,
+    StlAscii,
+    Glb,
+    Obj,
+    Ply,
+    ThreeMf,
+}
+
+impl MeshFormat {
+    pub fn label(self) -> &'static str {
+        match self {
+            MeshFormat::StlBinary => "STL (binary)",
+            MeshFormat::StlAscii => "STL (ASCII)",
+            MeshFormat::Glb => "glTF (binary)",
+            MeshFormat::Obj => "OBJ + MTL",
+            MeshFormat::Ply => "PLY",
+            MeshFormat::ThreeMf => "3MF",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MeshFormat::StlBinary | MeshFormat::StlAscii => "stl",
+            MeshFormat::Glb => "glb",
+            MeshFormat::Obj => "obj",
+            MeshFormat::Ply => "ply",
+            MeshFormat::ThreeMf => "3mf",
+        }
+    }
+
+    /// Whether the format can hold each body as a separate object.
+    pub fn supports_bodies(self) -> bool {
+        match self {
+            MeshFormat::StlBinary | MeshFormat::StlAscii | MeshFormat::Ply => false,
+            MeshFormat::Glb | MeshFormat::Obj | MeshFormat::ThreeMf => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mesher {
+    /// Marching cubes, which rounds off sharp edges.
+    Smooth,
+    /// Dual contouring, which keeps sharp edges and corners.
+    Sharp,
+    /// Dual contouring on an octree, merging cells where detail isn't needed.
+    Adaptive,
+}
+
+impl Mesher {
+    pub const ALL: [Mesher; 3] = [Mesher::Smooth, Mesher::Sharp, Mesher::Adaptive];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Mesher::Smooth => "Smooth",
+            Mesher::Sharp => "Sharp",
+            Mesher::Adaptive => "Adaptive",
+        }
+    }
+}
+
+/// Smoothing applied to the mesh before it's decimated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Smoothing {
+    Off,
+    /// Averages vertices with their neighbors, shrinking the mesh a little.
+    Laplacian,
+    /// Laplacian smoothing that pushes back out to keep the volume.
+    Taubin,
+}
+
+impl Smoothing {
+    pub const ALL: [Smoothing; 3] = [Smoothing::Off, Smoothing::Laplacian, Smoothing::Taubin];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Smoothing::Off => "Off",
+            Smoothing::Laplacian => "Laplacian",
+            Smoothing::Taubin => "Taubin",
+        }
+    }
+}
+
+/// Limits for reducing the triangle count, decimation stops at whichever
+/// is reached first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decimation {
+    pub target_triangles: usize,
+    /// How far the surface may move, in world units.
+    pub max_error: f32,
+}
+
+impl Default for Decimation {
+    fn default() -> Self {
+        Self {
+            target_triangles: 10_000,
+            max_error: 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExportSummary {
+    pub triangles: usize,
+    pub files: usize,
+}
+
+/// Problems found in an exported mesh, summed over its bodies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MeshReport {
+    pub triangles: usize,
+    /// Edges with a triangle on only one side, each hole is bordered by some.
+    pub boundary_edges: usize,
+    /// Edges shared by more than two triangles.
+    pub non_manifold_edges: usize,
+    /// Vertices where separate parts of the surface touch.
+    pub non_manifold_vertices: usize,
+    pub degenerate_triangles: usize,
+    /// Pairs of triangles passing through each other.
+    pub self_intersections: usize,
+}
+
+impl MeshReport {
+    /// Whether the surface is closed, enclosing a volume.
+    pub fn is_watertight(&self) -> bool {
+        self.boundary_edges == 0 && self.non_manifold_edges == 0
+    }
+
+    pub fn is_manifold(&self) -> bool {
+        self.non_manifold_edges == 0 && self.non_manifold_vertices == 0
+    }
+
+    fn add(&mut self, other: MeshReport) {
+        self.triangles += other.triangles;
+        self.boundary_edges += other.boundary_edges;
+        self.non_manifold_edges += other.non_manifold_edges;
+        self.non_manifold_vertices += other.non_manifold_vertices;
+        self.degenerate_triangles += other.degenerate_triangles;
+        self.self_intersections += other.self_intersections;
+    }
+}
+
+/// Meshed and post-processed bodies, ready to be written.
+#[derive(Debug, Clone)]
+pub struct PreparedExport {
+    pub format: MeshFormat,
+    pub options: ExportOptions,
+    pub meshes: Vec<(String, TriangleMesh)>,
+    pub report: MeshReport,
+}
+
+/// A solid exported on its own, named after the scene node it came from.
+#[derive(Debug, Clone)]
+pub struct Body {
+    pub name: String,
+    pub scene: sdf::Scene,
+}
+
+/// Split the scene into a body per node that adds geometry. Each body keeps
+/// the cuts made by nodes after it, but blends between bodies are lost.
+pub fn split_bodies(nodes: &[geometry::SceneNode]) -> Vec<Body> {
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| node.primitives.iter().any(|p| !p.is_subtract))
+        .map(|(i, node)| {
+            let cuts = nodes[i + 1..]
+                .iter()
+                .flat_map(|later| later.primitives.iter())
+                .filter(|p| p.is_subtract);
+
+            Body {
+                name: node.name.clone(),
+                scene: sdf::Scene::new(node.primitives.iter().chain(cuts).cloned().collect()),
+            }
+        })
+        .collect()
+}
+
+/// Mesh, post-process and check the scene for `format`, ready for [`write`].
+/// Progress is reported between stages, and cancelling stops at the next one.
+pub fn prepare(
+    nodes: &[geometry::SceneNode],
+    options: &ExportOptions,
+    format: MeshFormat,
+    progress: &ExportProgress,
+) -> Result<PreparedExport, ExportError> {
+    let nodes = &options.scope.nodes(nodes, &options.selection);
+
+    let bodies = if options.file_per_body || (options.separate_bodies && format.supports_bodies()) {
+        split_bodies(nodes)
+    } else {
+        let primitives = nodes.iter().flat_map(|node| node.primitives.clone());
+
+        vec![Body {
+            name: "Scene".to_string(),
+            scene: sdf::Scene::new(primitives.collect()),
+        }]
+    };
+
+    let count = bodies.len() as f32;
+    let mut meshes = Vec::new();
+    let mut report = MeshReport::default();
+
+    for (i, body) in bodies.into_iter().enumerate() {
+        let step = |stage: f32| progress.set((i as f32 + stage) / count);
+
+        let Some(bounds) = bounds::compute(&body.scene) else {
+            continue;
+        };
+
+        progress.stage(i as f32 / count..(i as f32 + 0.6) / count);
+        let field = progress.guard(|p| body.scene.map_geometry(p).dist);
+        let mut mesh = mesh_field(field, bounds, options, progress);
+        progress.check()?;
+        step(0.6);
+
+        smooth::smooth(&mut mesh, options.smoothing, options.smoothing_iterations);
+        if let Some(decimation) = options.decimation {
+            progress.check()?;
+            step(0.7);
+            mesh = decimate::decimate(&mesh, decimation);
+        }
+
+        progress.check()?;
+        step(0.8);
+
+        if matches!(format, MeshFormat::Glb | MeshFormat::Ply) {
+            mesh.sample_colors(&body.scene);
+        }
+        if matches!(format, MeshFormat::Obj | MeshFormat::Ply) {
+            mesh.sample_normals(&body.scene);
+        }
+        if matches!(format, MeshFormat::Obj | MeshFormat::ThreeMf) {
+            mesh.sample_face_colors(&body.scene);
+        }
+
+        progress.check()?;
+        step(0.9);
+
+        report.add(validate::check(&mesh));
+        meshes.push((body.name, mesh));
+    }
+
+    progress.check()?;
+    progress.set(1.0);
+
+    if meshes.is_empty() {
+        return Err(ExportError::EmptyScene);
+    }
+
+    Ok(PreparedExport {
+        format,
+        options: options.clone(),
+        meshes,
+        report,
+    })
+}
+
+/// Write prepared meshes to `path` in the format they were prepared for.
+/// With a file per body, each goes next to `path` as named by [`body_path`],
+/// and `progress` advances a file at a time. Cancelling stops before the
+/// next file.
+pub fn write(
+    prepared: &PreparedExport,
+    path: &Path,
+    progress: &ExportProgress,
+) -> Result<ExportSummary, ExportError> {
+    let (format, unit) = (prepared.format, prepared.options.unit);
+
+    if !prepared.options.file_per_body {
+        write_meshes(format, unit, &prepared.meshes, path)?;
+        progress.set(1.0);
+
+        return Ok(ExportSummary {
+            triangles: prepared.report.triangles,
+            files: 1,
+        });
+    }
+
+    for (i, body) in prepared.meshes.iter().enumerate() {
+        progress.check()?;
+        progress.advance(i, prepared.meshes.len());
+
+        write_meshes(
+            format,
+            unit,
+            std::slice::from_ref(body),
+            &body_path(path, &body.0),
+        )?;
+    }
+    progress.set(1.0);
+
+    Ok(ExportSummary {
+        triangles: prepared.report.triangles,
+        files: prepared.meshes.len(),
+    })
+}
+
+/// Where a body of `name` is written when each gets a file: beside `path`,
+/// with the name added to its stem in a form safe for file names.
+pub fn body_path(path: &Path, name: &str) -> PathBuf {
+    let slug: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    let slug = slug.split_whitespace().collect::<Vec<_>>().join("_");
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file = path.with_file_name(format!("{stem}_{slug}"));
+
+    match path.extension() {
+        Some(extension) => file.with_extension(extension),
+        None => file,
+    }
+}
+
+fn write_meshes(
+    format: MeshFormat,
+    unit: Unit,
+    meshes: &[(String, TriangleMesh)],
+    path: &Path,
+) -> Result<(), ExportError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    // Formats without objects get the bodies as one mesh
+    let combined = || {
+        let mut combined = TriangleMesh::default();
+        for (_, mesh) in meshes {
+            combined.append(mesh.clone());
+        }
+        combined
+    };
+
+    match format {
+        MeshFormat::StlBinary => stl::write_binary(&combined().into_z_up(), &mut writer)?,
+        MeshFormat::StlAscii => stl::write_ascii(&combined().into_z_up(), &mut writer)?,
+        MeshFormat::Ply => ply::write_ply(&combined(), &mut writer)?,
+        MeshFormat::Glb => gltf::write_glb(meshes, &mut writer)?,
+        MeshFormat::ThreeMf => threemf::write_3mf(meshes, unit, &mut writer)?,
+        MeshFormat::Obj => {
+            let materials_path = path.with_extension("mtl");
+            let materials_name = materials_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut materials = BufWriter::new(File::create(&materials_path)?);
+
+            obj::write_obj(meshes, &materials_name, &mut writer, &mut materials)?;
+            materials.flush()?;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bevy::math::bounding::{Aabb3d, BoundingVolume};
+
+    use super::*;
+    use crate::export::tests::{box_scene, node};
+
+    fn mesh_scene(scene: &sdf::Scene, bounds: Aabb3d, options: &ExportOptions) -> TriangleMesh {
+        let progress = ExportProgress::default();
+        mesh_field(|p| scene.map_geometry(p).dist, bounds, options, &progress)
+    }
+
+    fn signed_volume(mesh: &TriangleMesh) -> f32 {
+        mesh.triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| mesh.positions[i as usize]);
+                a.dot(b.cross(c)) / 6.0
+            })
+            .sum()
+    }
+
+    fn assert_closed(mesh: &TriangleMesh) {
+        let mut edges: HashMap<(u32, u32), i32> = HashMap::new();
+
+        for &[a, b, c] in &mesh.triangles {
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                // Each directed edge is cancelled by its opposite
+                *edges.entry((from.min(to), from.max(to))).or_default() +=
+                    if from < to { 1 } else { -1 };
+            }
+        }
+
+        assert!(edges.values().all(|count| *count == 0));
+    }
+
+    fn options(mesher: Mesher, resolution: u32) -> ExportOptions {
+        ExportOptions {
+            mesher,
+            resolution,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn meshing_moves_through_its_stage() {
+        let scene = box_scene();
+        let bounds = bounds::compute(&scene).unwrap();
+
+        for mesher in Mesher::ALL {
+            let progress = ExportProgress::default();
+            progress.stage(0.25..0.75);
+            mesh_field(
+                |p| scene.map_geometry(p).dist,
+                bounds,
+                &options(mesher, 24),
+                &progress,
+            );
+
+            // Past sampling, into meshing, and no further than the stage
+            let fraction = progress.fraction();
+            assert!(fraction > 0.5 && fraction <= 0.75, "{mesher:?} {fraction}");
+        }
+    }
+
+    #[test]
+    fn box_meshes_closed_and_outward() {
+        let scene = box_scene();
+        let bounds = bounds::compute(&scene).unwrap();
+
+        let mesh = mesh_scene(&scene, bounds, &options(Mesher::Smooth, 32));
+
+        assert!(!mesh.triangles.is_empty());
+        assert_closed(&mesh);
+
+        // Marching cubes cuts off a little at the corners
+        let volume = signed_volume(&mesh);
+        assert!((volume - 3.0).abs() < 0.1, "volume {volume}");
+
+        // Still outward after turning Z up
+        assert!((signed_volume(&mesh.into_z_up()) - volume).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sharp_mesher_keeps_corners() {
+        let scene = box_scene();
+        // Keep the faces off the grid planes
+        let bounds = bounds::compute(&scene).unwrap().grow(Vec3A::splat(0.0123));
+
+        let mesh = mesh_scene(&scene, bounds, &options(Mesher::Sharp, 24));
+
+        assert_closed(&mesh);
+        let volume = signed_volume(&mesh);
+        assert!((volume - 3.0).abs() < 0.01, "volume {volume}");
+
+        for x in [-1.0, 1.0] {
+            for y in [0.5, 1.5] {
+                for z in [-0.75, 0.75] {
+                    let corner = Vec3::new(x, y, z);
+                    let nearest = mesh
+                        .positions
+                        .iter()
+                        .map(|p| p.distance(corner))
+                        .fold(f32::INFINITY, f32::min);
+
+                    assert!(nearest < 0.01, "corner {corner} off by {nearest}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_mesher_merges_flat_faces() {
+        let scene = box_scene();
+        let bounds = bounds::compute(&scene).unwrap().grow(Vec3A::splat(0.0123));
+
+        let uniform = mesh_scene(&scene, bounds, &options(Mesher::Sharp, 64));
+        let adaptive = mesh_scene(&scene, bounds, &options(Mesher::Adaptive, 64));
+
+        assert_closed(&adaptive);
+        assert!((signed_volume(&adaptive) - 3.0).abs() < 0.01);
+        assert!(adaptive.triangles.len() * 10 < uniform.triangles.len());
+    }
+
+    #[test]
+    fn adaptive_mesher_joins_cells_of_different_sizes() {
+        // Rounding and blending curve the surface, so only some cells merge
+        let scene = sdf::Scene::new(vec![
+            geometry::BoxGeometry {
+                scale: Vec3::new(1.0, 0.5, 0.75),
+                rounding: 0.3,
+                ..geometry::BoxGeometry::new(Vec3::new(0.0, 1.0, 0.0), 0)
+            },
+            geometry::BoxGeometry {
+                scale: Vec3::splat(0.4),
+                blend: 0.2,
+                is_subtract: true,
+                ..geometry::BoxGeometry::new(Vec3::new(0.5, 1.5, 0.2), 1)
+            },
+        ]);
+        let bounds = bounds::compute(&scene).unwrap();
+
+        let uniform = mesh_scene(&scene, bounds, &options(Mesher::Sharp, 48));
+        let adaptive = mesh_scene(&scene, bounds, &options(Mesher::Adaptive, 48));
+
+        assert_closed(&adaptive);
+        assert!((signed_volume(&adaptive) - signed_volume(&uniform)).abs() < 0.01);
+        assert!(adaptive.triangles.len() < uniform.triangles.len());
+    }
+
+    #[test]
+    fn decimation_merges_flat_faces() {
+        let scene = box_scene();
+        let bounds = bounds::compute(&scene).unwrap().grow(Vec3A::splat(0.0123));
+
+        let dense = mesh_scene(&scene, bounds, &options(Mesher::Sharp, 24));
+        let decimated = decimate::decimate(
+            &dense,
+            Decimation {
+                target_triangles: 12,
+                max_error: 0.01,
+            },
+        );
+
+        assert_closed(&decimated);
+        assert!((signed_volume(&decimated) - 3.0).abs() < 0.01);
+        assert!(decimated.triangles.len() * 20 < dense.triangles.len());
+
+        let report = validate::check(&decimated);
+        assert!(report.is_manifold() && report.is_watertight());
+        assert_eq!(report.degenerate_triangles, 0);
+    }
+
+    #[test]
+    fn taubin_smoothing_keeps_volume() {
+        let scene = box_scene();
+        let bounds = bounds::compute(&scene).unwrap();
+        let mesh = mesh_scene(&scene, bounds, &options(Mesher::Smooth, 24));
+        let volume = signed_volume(&mesh);
+
+        let smoothed = |smoothing| {
+            let mut mesh = mesh.clone();
+            smooth::smooth(&mut mesh, smoothing, 10);
+            (signed_volume(&mesh) - volume).abs()
+        };
+
+        assert!(smoothed(Smoothing::Taubin) * 4.0 < smoothed(Smoothing::Laplacian));
+    }
+
+    #[test]
+    fn bodies_keep_later_cuts() {
+        let cut = geometry::BoxGeometry {
+            is_subtract: true,
+            ..geometry::BoxGeometry::new(Vec3::ZERO, 1)
+        };
+        let nodes = [
+            node(0, geometry::BoxGeometry::new(Vec3::ZERO, 0)),
+            node(1, cut),
+            node(2, geometry::BoxGeometry::new(Vec3::X * 10.0, 2)),
+        ];
+
+        let bodies = split_bodies(&nodes);
+
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0].name, "Box 0");
+        assert_eq!(bodies[0].scene.primitives().len(), 2);
+        assert_eq!(bodies[1].name, "Box 2");
+        assert_eq!(bodies[1].scene.primitives().len(), 1);
+    }
+
+    #[test]
+    fn bodies_get_files_named_after_their_nodes() {
+        assert_eq!(
+            body_path(Path::new("out/part.stl"), "Bracket (2)"),
+            PathBuf::from("out/part_bracket_2.stl")
+        );
+
+        let nodes = [
+            node(1, geometry::BoxGeometry::new(Vec3::ZERO, 1)),
+            node(2, geometry::BoxGeometry::new(Vec3::X * 10.0, 2)),
+        ];
+        let options = ExportOptions {
+            resolution: 16,
+            file_per_body: true,
+            ..default()
+        };
+
+        let prepared = prepare(
+            &nodes,
+            &options,
+            MeshFormat::StlBinary,
+            &ExportProgress::default(),
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join("raystacean_file_per_body.stl");
+        let summary = write(&prepared, &path, &ExportProgress::default()).unwrap();
+        assert_eq!(summary.files, 2);
+
+        for name in ["Box 1", "Box 2"] {
+            let body = body_path(&path, name);
+            assert!(std::fs::metadata(&body).unwrap().len() > 84);
+            std::fs::remove_file(body).unwrap();
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn prepared_meshes_are_checked() {
+        let nodes = [node(1, box_scene().primitives()[0].clone())];
+        let prepared = prepare(
+            &nodes,
+            &options(Mesher::Smooth, 16),
+            MeshFormat::StlBinary,
+            &ExportProgress::default(),
+        )
+        .unwrap();
+
+        let report = prepared.report;
+        assert_eq!(report.triangles, prepared.meshes[0].1.triangles.len());
+        assert!(report.is_watertight() && report.is_manifold());
+        assert_eq!(report.self_intersections, 0);
+    }
+
+    #[test]
+    fn checks_report_progress_and_stop_when_cancelled() {
+        let nodes = [node(1, box_scene().primitives()[0].clone())];
+        let progress = ExportProgress::default();
+
+        let prepared = prepare(
+            &nodes,
+            &options(Mesher::Sharp, 16),
+            MeshFormat::StlBinary,
+            &progress,
+        )
+        .unwrap();
+        assert_eq!(progress.fraction(), 1.0);
+
+        // A closed surface has three edges for every two triangles
+        let mesh = &prepared.meshes[0].1;
+        assert_eq!(mesh.edges().len() * 2, mesh.triangles.len() * 3);
+
+        progress.cancel();
+        let result = prepare(
+            &nodes,
+            &options(Mesher::Sharp, 16),
+            MeshFormat::StlBinary,
+            &progress,
+        );
+        assert!(matches!(result, Err(ExportError::Cancelled)));
+    }
+
+    #[test]
+    fn empty_scene_is_an_error() {
+        let result = prepare(
+            &[],
+            &ExportOptions::default(),
+            MeshFormat::StlBinary,
+            &ExportProgress::default(),
+        );
+
+        assert!(matches!(result, Err(ExportError::EmptyScene)));
+    }
+}
@@ -0,0 +1,125 @@
+use std::io::{self, Write};
+
+use bevy::prelude::*;
+
+use super::{TriangleMesh, display_color};
+
+/// Write Wavefront OBJ with an object per mesh and vertex normals when the
+/// meshes have them. Faces are grouped into a material per face color,
+/// written to `materials` and referenced from the OBJ as `materials_name`.
+pub fn write_obj(
+    meshes: &[(String, TriangleMesh)],
+    materials_name: &str,
+    writer: &mut impl Write,
+    materials: &mut impl Write,
+) -> io::Result<()> {
+    let mut palette: Vec<Vec3> = Vec::new();
+
+    writeln!(writer, "# raystacean")?;
+    writeln!(writer, "mtllib {materials_name}")?;
+
+    // Indices are 1 based and count every vertex written before
+    let mut offset = 1;
+
+    for (name, mesh) in meshes {
+        writeln!(writer, "o {name}")?;
+
+        for p in &mesh.positions {
+            writeln!(writer, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+
+        let has_normals = mesh.normals.len() == mesh.positions.len();
+        if has_normals {
+            for n in &mesh.normals {
+                writeln!(writer, "vn {} {} {}", n.x, n.y, n.z)?;
+            }
+        }
+
+        let mut material = |t: usize| {
+            let color = mesh.face_colors.get(t).copied().unwrap_or(Vec3::ONE);
+            palette.iter().position(|c| *c == color).unwrap_or_else(|| {
+                palette.push(color);
+                palette.len() - 1
+            })
+        };
+        let mut faces: Vec<(usize, usize)> = (0..mesh.triangles.len())
+            .map(|t| (material(t), t))
+            .collect();
+        faces.sort();
+
+        let mut current = None;
+
+        for (material, t) in faces {
+            if current != Some(material) {
+                writeln!(writer, "usemtl color_{material}")?;
+                current = Some(material);
+            }
+
+            let [a, b, c] = mesh.triangles[t].map(|i| i as usize + offset);
+            if has_normals {
+                writeln!(writer, "f {a}//{a} {b}//{b} {c}//{c}")?;
+            } else {
+                writeln!(writer, "f {a} {b} {c}")?;
+            }
+        }
+
+        offset += mesh.positions.len();
+    }
+
+    writeln!(materials, "# raystacean")?;
+
+    for (i, color) in palette.iter().enumerate() {
+        let [r, g, b, _] = display_color(*color).to_f32_array();
+
+        writeln!(materials, "newmtl color_{i}")?;
+        writeln!(materials, "Kd {r:.4} {g:.4} {b:.4}")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obj_layout() {
+        let triangle = TriangleMesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            triangles: vec![[0, 1, 2]],
+            normals: vec![Vec3::Z; 3],
+            face_colors: vec![Vec3::X],
+            ..default()
+        };
+        let second = TriangleMesh {
+            face_colors: vec![Vec3::Y],
+            normals: Vec::new(),
+            ..triangle.clone()
+        };
+
+        let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+        write_obj(
+            &[
+                ("Box 1".to_string(), triangle),
+                ("Box 2".to_string(), second),
+            ],
+            "scene.mtl",
+            &mut obj,
+            &mut mtl,
+        )
+        .unwrap();
+        let (obj, mtl) = (
+            String::from_utf8(obj).unwrap(),
+            String::from_utf8(mtl).unwrap(),
+        );
+
+        assert!(obj.contains("mtllib scene.mtl\n"));
+        assert!(obj.contains("o Box 1\n"));
+        assert_eq!(obj.matches("\nvn ").count(), 3);
+        assert!(obj.contains("usemtl color_0\nf 1//1 2//2 3//3\n"));
+        assert!(obj.contains("usemtl color_1\nf 4 5 6\n"));
+
+        assert!(mtl.contains("newmtl color_0\nKd 1.0000 0.0000 0.0000\n"));
+        assert!(mtl.contains("newmtl color_1\nKd 0.0000 1.0000 0.0000\n"));
+    }
+}
@@ -0,0 +1,535 @@
+use std::array;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bevy::math::bounding::Aabb3d;
+use bevy::prelude::*;
+
+use super::dual_contouring::{Crossing, Qef, surface_crossing};
+use super::{ExportProgress, TriangleMesh};
+
+/// Subtrees above this depth are built on threads of their own, and those
+/// at it are the steps progress is counted in.
+const PARALLEL_DEPTH: u32 = 2;
+
+enum Node {
+    /// Entirely inside or outside of the surface.
+    Empty {
+        inside: bool,
+    },
+    Leaf(Box<Leaf>),
+    Internal(Box<[Node; 8]>),
+}
+
+impl Node {
+    fn is_internal(&self) -> bool {
+        matches!(self, Node::Internal(_))
+    }
+
+    /// The child at `index`, or the node itself if it isn't subdivided.
+    fn child(&self, index: usize) -> &Node {
+        match self {
+            Node::Internal(children) => &children[index],
+            _ => self,
+        }
+    }
+
+    fn inside(&self, corner: usize) -> bool {
+        match self {
+            Node::Empty { inside } => *inside,
+            Node::Leaf(leaf) => leaf.inside(corner),
+            Node::Internal(_) => unreachable!("only terminal nodes have corner signs"),
+        }
+    }
+}
+
+/// A cell with a single vertex, either at the finest level or simplified
+/// from its children.
+struct Leaf {
+    depth: u32,
+    /// A bit per corner, set when the corner is inside.
+    corners: u8,
+    qef: Qef,
+    position: Vec3,
+    index: u32,
+}
+
+impl Leaf {
+    fn inside(&self, corner: usize) -> bool {
+        self.corners & (1 << corner) != 0
+    }
+}
+
+/// Extract the zero surface of `field` within `region` with dual
+/// contouring on an octree. Cells are only subdivided down to `cell_size`
+/// near the surface, then merged back together wherever a single vertex
+/// stays within `max_error` of every tangent plane below it, which
+/// collapses flat faces and sharp edges to a handful of triangles. The
+/// field must not overestimate distance, it's used to skip empty space.
+pub fn mesh(
+    field: impl Fn(Vec3) -> f32 + Sync,
+    region: Aabb3d,
+    cell_size: f32,
+    max_error: f32,
+    progress: &ExportProgress,
+) -> TriangleMesh {
+    let extent = Vec3::from(region.max - region.min).max_element();
+    let max_depth = (extent / cell_size).log2().ceil().max(1.0) as u32;
+
+    let builder = Builder {
+        field,
+        max_depth,
+        max_error,
+        root_size: cell_size * (1 << max_depth) as f32,
+        progress,
+        built: AtomicUsize::new(0),
+    };
+
+    let mut root = builder.build(Vec3::from(region.min), 0);
+
+    let mut mesh = TriangleMesh::default();
+    assign_vertices(&mut root, &mut mesh.positions);
+    cell_proc(&root, &mut mesh.triangles);
+
+    mesh
+}
+
+struct Builder<'a, F> {
+    field: F,
+    max_depth: u32,
+    max_error: f32,
+    root_size: f32,
+    progress: &'a ExportProgress,
+    /// Subtrees at the parallel depth built so far.
+    built: AtomicUsize,
+}
+
+impl<F: Fn(Vec3) -> f32 + Sync> Builder<'_, F> {
+    fn build(&self, min: Vec3, depth: u32) -> Node {
+        let size = self.root_size / (1 << depth) as f32;
+
+        // The surface is at least this far from the center, so doesn't
+        // reach the cube when it's further than the corners
+        let d = (self.field)(min + Vec3::splat(size * 0.5));
+        if d.abs() > size * 0.5 * 3.0f32.sqrt() {
+            self.finished(depth);
+            return Node::Empty { inside: d < 0.0 };
+        }
+
+        let children = if depth + 1 == self.max_depth {
+            self.leaves(min, size, depth + 1)
+        } else {
+            let child = |i: usize| self.build(min + corner_offset(i) * size * 0.5, depth + 1);
+
+            if depth < PARALLEL_DEPTH {
+                std::thread::scope(|s| {
+                    array::from_fn::<_, 8, _>(|i| s.spawn(move || child(i)))
+                        .map(|handle| handle.join().expect("meshing thread panicked"))
+                })
+            } else {
+                array::from_fn(child)
+            }
+        };
+
+        let node = self.simplify(children, min, size, depth);
+
+        // Deeper subtrees were counted as they finished
+        if depth == PARALLEL_DEPTH || depth + 1 == self.max_depth {
+            self.finished(depth);
+        }
+
+        node
+    }
+
+    /// Count a node at `depth` as built, standing for every subtree at the
+    /// parallel depth below it.
+    fn finished(&self, depth: u32) {
+        if depth > PARALLEL_DEPTH {
+            return;
+        }
+
+        let subtrees = 8usize.pow(PARALLEL_DEPTH - depth);
+        let done = self.built.fetch_add(subtrees, Ordering::Relaxed) + subtrees;
+        self.progress.advance(done, 8usize.pow(PARALLEL_DEPTH));
+    }
+
+    /// The eight finest cells of a node of `size`. They're built together so
+    /// the samples and crossings they share are only found once.
+    fn leaves(&self, min: Vec3, size: f32, depth: u32) -> [Node; 8] {
+        let cell_size = size * 0.5;
+        let position = |p: UVec3| min + p.as_vec3() * cell_size;
+
+        let values: [f32; 27] = array::from_fn(|i| (self.field)(position(lattice_point(i))));
+        let inside = |p: UVec3| values[lattice_index(p)] < 0.0;
+
+        // Crossings on the lattice edges, by lower end and axis
+        let crossings: [[Option<Crossing>; 3]; 27] = array::from_fn(|i| {
+            let start = lattice_point(i);
+
+            array::from_fn(|axis| {
+                let end = start + UVec3::AXES[axis];
+                if end[axis] > 2 || inside(start) == inside(end) {
+                    return None;
+                }
+
+                let (a, b) = (position(start), position(end));
+                let (a, b) = if inside(start) { (a, b) } else { (b, a) };
+                Some(surface_crossing(&self.field, a, b, cell_size * 0.01))
+            })
+        });
+
+        array::from_fn(|child| {
+            let offset = corner_offset(child).as_uvec3();
+
+            let corners = (0..8)
+                .filter(|&corner| inside(offset + corner_offset(corner).as_uvec3()))
+                .fold(0u8, |corners, corner| corners | (1 << corner));
+
+            if corners == 0 || corners == u8::MAX {
+                return Node::Empty {
+                    inside: corners != 0,
+                };
+            }
+
+            let mut qef = Qef::default();
+
+            for (a, b) in cell_edges() {
+                let start = offset + corner_offset(a).as_uvec3();
+                let axis = (b - a).trailing_zeros() as usize;
+
+                if let Some(crossing) = crossings[lattice_index(start)][axis] {
+                    qef.add(crossing);
+                }
+            }
+
+            let cell_min = position(offset);
+
+            Node::Leaf(Box::new(Leaf {
+                depth,
+                corners,
+                qef,
+                position: qef.place(cell_min, cell_min + cell_size),
+                index: 0,
+            }))
+        })
+    }
+
+    /// Replace the children with a single leaf, if they're all terminal,
+    /// the merged vertex is within the error, and doing so can't change the
+    /// topology of the surface.
+    fn simplify(&self, children: [Node; 8], min: Vec3, size: f32, depth: u32) -> Node {
+        if children.iter().any(Node::is_internal) {
+            return Node::Internal(Box::new(children));
+        }
+
+        if let Node::Empty { inside } = children[0]
+            && children
+                .iter()
+                .all(|child| matches!(child, Node::Empty { inside: i } if *i == inside))
+        {
+            return Node::Empty { inside };
+        }
+
+        // Signs on the 3x3x3 lattice of child corners, in half cell steps
+        let sign = |p: UVec3| {
+            let child = p / 2;
+            let corner = p.min(UVec3::ONE);
+            children[corner_index(child)].inside(corner_index(corner))
+        };
+
+        let corners = (0..8)
+            .filter(|&corner| sign(corner_offset(corner).as_uvec3() * 2))
+            .fold(0u8, |corners, corner| corners | (1 << corner));
+
+        let children_manifold = children.iter().all(|child| match child {
+            Node::Leaf(leaf) => is_manifold(leaf.corners),
+            _ => true,
+        });
+
+        if !is_manifold(corners) || !children_manifold {
+            return Node::Internal(Box::new(children));
+        }
+
+        let mut qef = Qef::default();
+        for child in &children {
+            if let Node::Leaf(leaf) = child {
+                qef.merge(&leaf.qef);
+            }
+        }
+
+        let position = qef.place(min, min + Vec3::splat(size));
+        if qef.error(position) > self.max_error * self.max_error || !preserves_signs(corners, sign)
+        {
+            return Node::Internal(Box::new(children));
+        }
+
+        Node::Leaf(Box::new(Leaf {
+            depth,
+            corners,
+            qef,
+            position,
+            index: 0,
+        }))
+    }
+}
+
+/// Whether the sign at each midpoint of the cell's edges and faces, and at
+/// its center, matches at least one corner of that edge, face or cube.
+/// Otherwise the children see surface the parent's corners would miss.
+fn preserves_signs(corners: u8, sign: impl Fn(UVec3) -> bool) -> bool {
+    (0..27).all(|i| {
+        let matching = if sign(lattice_point(i)) {
+            corners
+        } else {
+            !corners
+        };
+
+        matching & LATTICE_CORNERS[i] != 0
+    })
+}
+
+/// For each lattice point, the corners on the same edge, face or the whole
+/// cube as it.
+const LATTICE_CORNERS: [u8; 27] = {
+    let mut table = [0; 27];
+    let mut i = 0;
+
+    while i < 27 {
+        let p = [i % 3, i / 3 % 3, i / 9];
+        let mut corner = 0;
+
+        while corner < 8 {
+            let c = [
+                (corner & 1) * 2,
+                (corner >> 1 & 1) * 2,
+                (corner >> 2 & 1) * 2,
+            ];
+
+            if (p[0] == 1 || p[0] == c[0])
+                && (p[1] == 1 || p[1] == c[1])
+                && (p[2] == 1 || p[2] == c[2])
+            {
+                table[i] |= 1 << corner;
+            }
+
+            corner += 1;
+        }
+
+        i += 1;
+    }
+
+    table
+};
+
+/// Whether the inside corners are all connected along the cell's edges,
+/// and likewise the outside, so the surface through it is one sheet.
+fn is_manifold(corners: u8) -> bool {
+    is_connected(corners) && is_connected(!corners)
+}
+
+fn is_connected(set: u8) -> bool {
+    if set == 0 {
+        return true;
+    }
+
+    let mut reached = set & set.wrapping_neg();
+
+    loop {
+        // Step along each axis, flipping that bit of the corner index
+        let neighbors = ((reached & 0x55) << 1)
+            | ((reached & 0xaa) >> 1)
+            | ((reached & 0x33) << 2)
+            | ((reached & 0xcc) >> 2)
+            | ((reached & 0x0f) << 4)
+            | ((reached & 0xf0) >> 4);
+
+        let grown = reached | (neighbors & set);
+        if grown == reached {
+            return reached == set;
+        }
+
+        reached = grown;
+    }
+}
+
+fn assign_vertices(node: &mut Node, positions: &mut Vec<Vec3>) {
+    match node {
+        Node::Empty { .. } => {}
+        Node::Leaf(leaf) => {
+            leaf.index = positions.len() as u32;
+            positions.push(leaf.position);
+        }
+        Node::Internal(children) => {
+            for child in children.iter_mut() {
+                assign_vertices(child, positions);
+            }
+        }
+    }
+}
+
+// Contouring walks every face and edge shared between cells, down to the
+// smallest cells either side, so neighbours of different sizes still join
+// up without cracks. Nodes around an edge along `axis` are ordered by
+// their side along the two other axes, in turn.
+
+fn cell_proc(node: &Node, triangles: &mut Vec<[u32; 3]>) {
+    let Node::Internal(children) = node else {
+        return;
+    };
+
+    for child in children.iter() {
+        cell_proc(child, triangles);
+    }
+
+    for axis in 0..3 {
+        let (u, v) = perpendicular(axis);
+
+        for i in 0..4 {
+            let lower = (i & 1) << u | (i >> 1) << v;
+            face_proc(
+                [&children[lower], &children[lower | 1 << axis]],
+                axis,
+                triangles,
+            );
+        }
+
+        for half in 0..2 {
+            let nodes = array::from_fn(|k| &children[half << axis | (k & 1) << u | (k >> 1) << v]);
+            edge_proc(nodes, axis, triangles);
+        }
+    }
+}
+
+fn face_proc(nodes: [&Node; 2], axis: usize, triangles: &mut Vec<[u32; 3]>) {
+    if !nodes.iter().any(|node| node.is_internal()) {
+        return;
+    }
+
+    let (u, v) = perpendicular(axis);
+
+    for i in 0..4 {
+        let offset = (i & 1) << u | (i >> 1) << v;
+        face_proc(
+            [nodes[0].child(offset | 1 << axis), nodes[1].child(offset)],
+            axis,
+            triangles,
+        );
+    }
+
+    // The edges within the face, along each of its axes
+    for (edge_axis, across) in [(u, v), (v, u)] {
+        let (pu, _) = perpendicular(edge_axis);
+
+        for half in 0..2 {
+            let edge_nodes = array::from_fn(|k| {
+                let side = |a: usize| if a == pu { k & 1 } else { k >> 1 };
+                let node = nodes[side(axis)];
+
+                node.child((1 - side(axis)) << axis | side(across) << across | half << edge_axis)
+            });
+
+            edge_proc(edge_nodes, edge_axis, triangles);
+        }
+    }
+}
+
+fn edge_proc(nodes: [&Node; 4], axis: usize, triangles: &mut Vec<[u32; 3]>) {
+    if !nodes.iter().any(|node| node.is_internal()) {
+        process_edge(nodes, axis, triangles);
+        return;
+    }
+
+    let (u, v) = perpendicular(axis);
+
+    for half in 0..2 {
+        let children = array::from_fn(|k| {
+            nodes[k].child(half << axis | (1 - (k & 1)) << u | (1 - (k >> 1)) << v)
+        });
+
+        edge_proc(children, axis, triangles);
+    }
+}
+
+/// Join the vertices of the cells around an edge if the surface crosses it,
+/// judged by the smallest cell.
+fn process_edge(nodes: [&Node; 4], axis: usize, triangles: &mut Vec<[u32; 3]>) {
+    let leaves = nodes.map(|node| match node {
+        Node::Leaf(leaf) => Some(leaf),
+        _ => None,
+    });
+
+    // Surface can't cross an edge of an empty cell
+    let [Some(a), Some(b), Some(c), Some(d)] = leaves else {
+        return;
+    };
+    let leaves = [a, b, c, d];
+
+    let smallest = (0..4)
+        .max_by_key(|&k| leaves[k].depth)
+        .expect("four leaves");
+
+    let (u, v) = perpendicular(axis);
+    let start = (1 - (smallest & 1)) << u | (1 - (smallest >> 1)) << v;
+    let start_inside = leaves[smallest].inside(start);
+
+    if start_inside == leaves[smallest].inside(start | 1 << axis) {
+        return;
+    }
+
+    // Counter-clockwise seen from the outside end of the edge
+    let mut ring: Vec<u32> = [0, 1, 3, 2].map(|k| leaves[k].index).to_vec();
+    if !start_inside {
+        ring.reverse();
+    }
+
+    // Neighbours larger than the smallest cell can appear twice
+    ring.dedup();
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+
+    match ring[..] {
+        [a, b, c, d] => {
+            triangles.push([a, b, c]);
+            triangles.push([a, c, d]);
+        }
+        [a, b, c] => triangles.push([a, b, c]),
+        _ => {}
+    }
+}
+
+fn perpendicular(axis: usize) -> (usize, usize) {
+    ((axis + 1) % 3, (axis + 2) % 3)
+}
+
+/// Children and corners are indexed by a bit per axis, x lowest.
+fn corner_offset(index: usize) -> Vec3 {
+    Vec3::new(
+        (index & 1) as f32,
+        (index >> 1 & 1) as f32,
+        (index >> 2 & 1) as f32,
+    )
+}
+
+fn corner_index(offset: UVec3) -> usize {
+    (offset.x | offset.y << 1 | offset.z << 2) as usize
+}
+
+/// Points of the 3x3x3 lattice spanning a node's children, x fastest.
+fn lattice_point(index: usize) -> UVec3 {
+    let index = index as u32;
+    UVec3::new(index % 3, index / 3 % 3, index / 9)
+}
+
+fn lattice_index(p: UVec3) -> usize {
+    (p.x + 3 * (p.y + 3 * p.z)) as usize
+}
+
+/// The twelve edges of a cell as pairs of corners.
+fn cell_edges() -> impl Iterator<Item = (usize, usize)> {
+    (0..8).flat_map(|corner| {
+        [1, 2, 4]
+            .into_iter()
+            .filter(move |bit| corner & bit == 0)
+            .map(move |bit| (corner, corner | bit))
+    })
+}
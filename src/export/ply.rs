@@ -0,0 +1,98 @@
+use std::io::{self, Write};
+
+use bevy::color::ColorToPacked;
+
+use super::{TriangleMesh, display_color};
+
+/// Write binary little endian PLY, with normals and 8 bit colors per vertex
+/// when the mesh has them. Without triangles it's written as a point cloud.
+pub fn write_ply(mesh: &TriangleMesh, writer: &mut impl Write) -> io::Result<()> {
+    let has_normals = mesh.normals.len() == mesh.positions.len();
+    let has_colors = mesh.colors.len() == mesh.positions.len();
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format binary_little_endian 1.0")?;
+    writeln!(writer, "comment raystacean")?;
+    writeln!(writer, "element vertex {}", mesh.positions.len())?;
+    for axis in ["x", "y", "z"] {
+        writeln!(writer, "property float {axis}")?;
+    }
+    if has_normals {
+        for axis in ["nx", "ny", "nz"] {
+            writeln!(writer, "property float {axis}")?;
+        }
+    }
+    if has_colors {
+        for channel in ["red", "green", "blue"] {
+            writeln!(writer, "property uchar {channel}")?;
+        }
+    }
+    if !mesh.triangles.is_empty() {
+        writeln!(writer, "element face {}", mesh.triangles.len())?;
+        writeln!(writer, "property list uchar uint vertex_indices")?;
+    }
+    writeln!(writer, "end_header")?;
+
+    for (i, p) in mesh.positions.iter().enumerate() {
+        for component in p.to_array() {
+            writer.write_all(&component.to_le_bytes())?;
+        }
+
+        if has_normals {
+            for component in mesh.normals[i].to_array() {
+                writer.write_all(&component.to_le_bytes())?;
+            }
+        }
+
+        if has_colors {
+            let [r, g, b, _] = display_color(mesh.colors[i]).to_u8_array();
+            writer.write_all(&[r, g, b])?;
+        }
+    }
+
+    for triangle in &mesh.triangles {
+        writer.write_all(&[3])?;
+        for index in triangle {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn ply_layout() {
+        let mesh = TriangleMesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            triangles: vec![[0, 1, 2]],
+            normals: vec![Vec3::Z; 3],
+            colors: vec![Vec3::new(1.0, 0.0, 0.0); 3],
+            ..default()
+        };
+
+        let mut bytes = Vec::new();
+        write_ply(&mesh, &mut bytes).unwrap();
+
+        let header_end = b"end_header\n";
+        let body = bytes
+            .windows(header_end.len())
+            .position(|w| w == header_end)
+            .unwrap()
+            + header_end.len();
+        let header = String::from_utf8(bytes[..body].to_vec()).unwrap();
+
+        assert!(header.contains("element vertex 3\n"));
+        assert!(header.contains("property uchar red\n"));
+        assert!(header.contains("element face 1\n"));
+
+        // 27 bytes per vertex, then a count and three indices
+        assert_eq!(bytes.len() - body, 3 * 27 + 13);
+        assert_eq!(&bytes[body + 24..body + 27], &[255, 0, 0]);
+    }
+}
@@ -0,0 +1,400 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bevy::color::ColorToPacked;
+use bevy::prelude::*;
+
+use super::{
+    ExportError, ExportOptions, ExportProgress, TriangleMesh, display_color, mesh_field, ply,
+    scoped_scene,
+};
+use crate::{bounds, geometry, sdf};
+
+/// Steps along the gradient taken to pull a point onto the surface.
+const PROJECTION_STEPS: usize = 4;
+/// Close enough to the surface to stop stepping, in world units.
+const PROJECTION_TOLERANCE: f32 = 1.0e-5;
+/// Points projected between progress updates and checks for cancelling.
+const PROGRESS_INTERVAL: usize = 1024;
+
+/// Formats holding points sampled on the surface, with normals and colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointsFormat {
+    Ply,
+    Xyz,
+}
+
+impl PointsFormat {
+    pub fn label(self) -> &'static str {
+        match self {
+            PointsFormat::Ply => "Point cloud (PLY)",
+            PointsFormat::Xyz => "Point cloud (XYZ)",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PointsFormat::Ply => "ply",
+            PointsFormat::Xyz => "xyz",
+        }
+    }
+}
+
+/// How many points a point cloud has, and the seed scattering them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PointSampling {
+    pub count: usize,
+    pub seed: u64,
+}
+
+impl Default for PointSampling {
+    fn default() -> Self {
+        Self {
+            count: 100_000,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointsSummary {
+    pub points: usize,
+}
+
+/// Points on the surface, each with its normal and linear color.
+#[derive(Debug, Clone, Default)]
+pub struct PointCloud {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub colors: Vec<Vec3>,
+}
+
+impl PointCloud {
+    /// The points as a mesh without triangles, for writers that handle both.
+    pub fn into_mesh(self) -> TriangleMesh {
+        TriangleMesh {
+            positions: self.positions,
+            normals: self.normals,
+            colors: self.colors,
+            ..default()
+        }
+    }
+}
+
+/// Scatter points over `mesh`, each triangle getting a share by its area so
+/// they spread evenly, then pull them onto the zero surface of `scene` along
+/// its gradient. The same seed always gives the same points.
+///
+/// Projecting advances `progress` through its current stage. Once it is
+/// cancelled, projecting stops and the rest of the points stay unplaced.
+pub fn sample(
+    mesh: &TriangleMesh,
+    scene: &sdf::Scene,
+    sampling: PointSampling,
+    progress: &ExportProgress,
+) -> PointCloud {
+    let corners = |t: &[u32; 3]| t.map(|i| mesh.positions[i as usize]);
+
+    // Running total of area, to pick triangles in proportion to theirs
+    let mut total = 0.0;
+    let areas: Vec<f32> = mesh
+        .triangles
+        .iter()
+        .map(|t| {
+            let [a, b, c] = corners(t);
+            total += (b - a).cross(c - a).length() * 0.5;
+            total
+        })
+        .collect();
+
+    if total <= 0.0 {
+        return PointCloud::default();
+    }
+
+    let mut random = SplitMix64(sampling.seed);
+    let starts: Vec<Vec3> = (0..sampling.count)
+        .map(|_| {
+            let target = random.next_f32() * total;
+            let triangle = areas
+                .partition_point(|area| *area < target)
+                .min(areas.len() - 1);
+            let [a, b, c] = corners(&mesh.triangles[triangle]);
+
+            // Uniform over the triangle
+            let (r1, r2) = (random.next_f32().sqrt(), random.next_f32());
+            a * (1.0 - r1) + b * (r1 * (1.0 - r2)) + c * (r1 * r2)
+        })
+        .collect();
+
+    let mut cloud = PointCloud {
+        positions: vec![Vec3::ZERO; starts.len()],
+        normals: vec![Vec3::ZERO; starts.len()],
+        colors: vec![Vec3::ZERO; starts.len()],
+    };
+
+    // Projecting is most of the work, shared out in chunks
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = starts.len().div_ceil(threads).max(1);
+    let (projected, total) = (AtomicUsize::new(0), starts.len());
+
+    std::thread::scope(|s| {
+        for (((starts, positions), normals), colors) in starts
+            .chunks(chunk)
+            .zip(cloud.positions.chunks_mut(chunk))
+            .zip(cloud.normals.chunks_mut(chunk))
+            .zip(cloud.colors.chunks_mut(chunk))
+        {
+            let projected = &projected;
+            s.spawn(move || {
+                for (i, start) in starts.iter().enumerate() {
+                    if i % PROGRESS_INTERVAL == 0 {
+                        if progress.is_cancelled() {
+                            return;
+                        }
+                        let batch = i.min(PROGRESS_INTERVAL);
+                        let done = projected.fetch_add(batch, Ordering::Relaxed) + batch;
+                        progress.advance(done, total);
+                    }
+
+                    let p = project(scene, *start);
+                    positions[i] = p;
+                    normals[i] = scene.geometry_normal(p);
+                    colors[i] = scene.map_geometry(p).color;
+                }
+            });
+        }
+    });
+
+    cloud
+}
+
+fn project(scene: &sdf::Scene, mut p: Vec3) -> Vec3 {
+    for _ in 0..PROJECTION_STEPS {
+        let distance = scene.map_geometry(p).dist;
+        if distance.abs() < PROJECTION_TOLERANCE {
+            break;
+        }
+
+        p -= scene.geometry_normal(p) * distance;
+    }
+
+    p
+}
+
+/// Scatter points evenly over the surface of the whole scene and write
+/// them to `path` with their normals and colors.
+pub fn export_points(
+    nodes: &[geometry::SceneNode],
+    options: &ExportOptions,
+    format: PointsFormat,
+    path: &Path,
+    progress: &ExportProgress,
+) -> Result<PointsSummary, ExportError> {
+    let scene = scoped_scene(nodes, options);
+    let bounds = bounds::compute(&scene).ok_or(ExportError::EmptyScene)?;
+
+    // The mesh only says roughly where the surface is, and how much of it
+    progress.stage(0.0..0.5);
+    let mesh = mesh_field(
+        progress.guard(|p| scene.map_geometry(p).dist),
+        bounds,
+        options,
+        progress,
+    );
+    progress.check()?;
+
+    progress.stage(0.5..1.0);
+    let cloud = sample(&mesh, &scene, options.points, progress);
+    progress.check()?;
+    if cloud.positions.is_empty() {
+        return Err(ExportError::EmptyScene);
+    }
+
+    let points = cloud.positions.len();
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        PointsFormat::Ply => ply::write_ply(&cloud.into_mesh(), &mut writer)?,
+        PointsFormat::Xyz => write_xyz(&cloud, &mut writer)?,
+    }
+
+    writer.flush()?;
+    progress.set(1.0);
+
+    Ok(PointsSummary { points })
+}
+
+/// Write ASCII XYZ, one point per line as `x y z nx ny nz r g b` with 8 bit
+/// colors.
+pub fn write_xyz(cloud: &PointCloud, writer: &mut impl Write) -> io::Result<()> {
+    for ((p, n), color) in cloud
+        .positions
+        .iter()
+        .zip(&cloud.normals)
+        .zip(&cloud.colors)
+    {
+        let [r, g, b, _] = display_color(*color).to_u8_array();
+        writeln!(
+            writer,
+            "{} {} {} {} {} {} {r} {g} {b}",
+            p.x, p.y, p.z, n.x, n.y, n.z
+        )?;
+    }
+
+    Ok(())
+}
+
+/// A small, fast generator, plenty for scattering points.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::{box_scene, node};
+
+    fn sphere_ish() -> (TriangleMesh, sdf::Scene) {
+        let scene = sdf::Scene::new(vec![geometry::BoxGeometry {
+            rounding: 1.0,
+            scale: Vec3::ONE,
+            ..geometry::BoxGeometry::new(Vec3::ZERO, 1)
+        }]);
+
+        // An octahedron inside, well off the surface
+        let mesh = TriangleMesh {
+            positions: vec![
+                Vec3::X,
+                Vec3::NEG_X,
+                Vec3::Y,
+                Vec3::NEG_Y,
+                Vec3::Z,
+                Vec3::NEG_Z,
+            ]
+            .into_iter()
+            .map(|p| p * 0.5)
+            .collect(),
+            triangles: vec![
+                [0, 2, 4],
+                [2, 1, 4],
+                [1, 3, 4],
+                [3, 0, 4],
+                [2, 0, 5],
+                [1, 2, 5],
+                [3, 1, 5],
+                [0, 3, 5],
+            ],
+            ..default()
+        };
+
+        (mesh, scene)
+    }
+
+    #[test]
+    fn points_land_on_the_surface_facing_out() {
+        let (mesh, scene) = sphere_ish();
+        let sampling = PointSampling {
+            count: 500,
+            seed: 7,
+        };
+
+        let cloud = sample(&mesh, &scene, sampling, &ExportProgress::default());
+        assert_eq!(cloud.positions.len(), 500);
+
+        for (p, n) in cloud.positions.iter().zip(&cloud.normals) {
+            assert!(scene.map_geometry(*p).dist.abs() < 1.0e-3, "{p}");
+            assert!(n.dot(p.normalize()) > 0.9, "{p} {n}");
+        }
+
+        // Seeded, so repeatable
+        let again = sample(&mesh, &scene, sampling, &ExportProgress::default());
+        assert_eq!(cloud.positions, again.positions);
+    }
+
+    #[test]
+    fn cancelled_sampling_places_nothing() {
+        let (mesh, scene) = sphere_ish();
+        let sampling = PointSampling {
+            count: 500,
+            seed: 7,
+        };
+
+        let progress = ExportProgress::default();
+        progress.cancel();
+        let cloud = sample(&mesh, &scene, sampling, &progress);
+
+        assert!(cloud.positions.iter().all(|p| *p == Vec3::ZERO));
+    }
+
+    #[test]
+    fn cancelled_point_exports_leave_no_file() {
+        let nodes = [node(1, box_scene().primitives()[0].clone())];
+        let path = std::env::temp_dir().join("raystacean_cancelled_point_exports.xyz");
+        let options = ExportOptions {
+            resolution: 16,
+            ..default()
+        };
+
+        let progress = ExportProgress::default();
+        progress.cancel();
+        let result = export_points(&nodes, &options, PointsFormat::Xyz, &path, &progress);
+
+        assert!(matches!(result, Err(ExportError::Cancelled)));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn xyz_has_a_line_per_point() {
+        let cloud = PointCloud {
+            positions: vec![Vec3::new(1.0, 2.0, 3.0), Vec3::ZERO],
+            normals: vec![Vec3::Y; 2],
+            colors: vec![Vec3::ONE; 2],
+        };
+
+        let mut bytes = Vec::new();
+        write_xyz(&cloud, &mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+
+        assert_eq!(text.lines().count(), 2);
+        assert_eq!(text.lines().next(), Some("1 2 3 0 1 0 255 255 255"));
+    }
+
+    #[test]
+    fn point_clouds_have_no_faces() {
+        let nodes = [node(1, box_scene().primitives()[0].clone())];
+        let path = std::env::temp_dir().join("raystacean_point_clouds_have_no_faces.ply");
+        let options = ExportOptions {
+            resolution: 16,
+            points: PointSampling {
+                count: 1000,
+                seed: 1,
+            },
+            ..default()
+        };
+
+        let progress = ExportProgress::default();
+        let summary = export_points(&nodes, &options, PointsFormat::Ply, &path, &progress).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(summary.points, 1000);
+        let header = String::from_utf8_lossy(&bytes[..200]);
+        assert!(header.contains("element vertex 1000\n"));
+        assert!(!header.contains("element face"));
+    }
+}
@@ -0,0 +1,301 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use bevy::math::bounding::Aabb3d;
+use bevy::prelude::*;
+
+use super::{
+    ExportError, ExportOptions, ExportProgress, MAX_RESOLUTION, MIN_RESOLUTION, Unit,
+    marching_squares, scoped_scene,
+};
+use crate::{bounds, geometry};
+
+/// Formats holding the outlines where the section plane cuts the geometry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionFormat {
+    Svg,
+    Dxf,
+}
+
+impl SectionFormat {
+    pub fn label(self) -> &'static str {
+        match self {
+            SectionFormat::Svg => "Section (SVG)",
+            SectionFormat::Dxf => "Section (DXF)",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SectionFormat::Svg => "svg",
+            SectionFormat::Dxf => "dxf",
+        }
+    }
+}
+
+/// A plane across one of the scene's axes to cut a cross-section on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SectionPlane {
+    /// 0, 1 or 2 for a plane across X, Y or Z.
+    pub axis: usize,
+    /// Where the plane crosses its axis.
+    pub offset: f32,
+}
+
+impl SectionPlane {
+    pub const AXIS_LABELS: [&str; 3] = ["X", "Y", "Z"];
+
+    pub fn normal(self) -> Vec3 {
+        Vec3::AXES[self.axis]
+    }
+
+    /// The scene directions of the drawing's x and y, as if looking at the
+    /// plane from the positive side: from the right, above or the front.
+    pub fn drawing_axes(self) -> (Vec3, Vec3) {
+        match self.axis {
+            0 => (Vec3::NEG_Z, Vec3::Y),
+            1 => (Vec3::X, Vec3::NEG_Z),
+            _ => (Vec3::X, Vec3::Y),
+        }
+    }
+
+    /// A point in the drawing, in the scene.
+    pub fn to_world(self, point: Vec2) -> Vec3 {
+        let (x, y) = self.drawing_axes();
+        x * point.x + y * point.y + self.normal() * self.offset
+    }
+
+    /// The corners of `bounds` seen in the drawing.
+    pub fn extent(self, bounds: Aabb3d) -> (Vec2, Vec2) {
+        let (x, y) = self.drawing_axes();
+        let (min, max) = (Vec3::from(bounds.min), Vec3::from(bounds.max));
+
+        (0..8)
+            .map(|i| Vec3::select(BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0), max, min))
+            .map(|corner| Vec2::new(corner.dot(x), corner.dot(y)))
+            .fold((Vec2::INFINITY, Vec2::NEG_INFINITY), |(min, max), p| {
+                (min.min(p), max.max(p))
+            })
+    }
+}
+
+impl Default for SectionPlane {
+    fn default() -> Self {
+        Self {
+            axis: 1,
+            offset: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SectionSummary {
+    pub contours: usize,
+}
+
+/// Trace where the section plane cuts the whole scene and write the
+/// outlines to `path`, in scene units.
+pub fn export_section(
+    nodes: &[geometry::SceneNode],
+    options: &ExportOptions,
+    format: SectionFormat,
+    path: &Path,
+    progress: &ExportProgress,
+) -> Result<SectionSummary, ExportError> {
+    let scene = scoped_scene(nodes, options);
+    let bounds = bounds::compute(&scene).ok_or(ExportError::EmptyScene)?;
+
+    let plane = options.section;
+    let (min, max) = plane.extent(bounds);
+    let resolution = options.resolution.clamp(MIN_RESOLUTION, MAX_RESOLUTION);
+    let cell_size = (max - min).max_element() / resolution as f32;
+
+    // A cell of margin so the outlines close at the edges of the bounds
+    let field = progress.guard(|p| scene.map_geometry(p).dist);
+    let loops = marching_squares::contours(
+        |p| field(plane.to_world(p)),
+        min - cell_size,
+        max + cell_size,
+        cell_size,
+    );
+    progress.check()?;
+    progress.set(0.9);
+
+    if loops.is_empty() {
+        return Err(ExportError::EmptySection);
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        SectionFormat::Svg => write_svg(&loops, options.unit, &mut writer)?,
+        SectionFormat::Dxf => write_dxf(&loops, options.unit, &mut writer)?,
+    }
+    writer.flush()?;
+    progress.set(1.0);
+
+    Ok(SectionSummary {
+        contours: loops.len(),
+    })
+}
+
+/// Write the loops as SVG, one path each, filled even-odd so holes show.
+/// The view box is in scene units, the page sized so one scene unit is one
+/// `unit`. SVG's y runs down, so the drawing is flipped to stay upright.
+pub fn write_svg(loops: &[Vec<Vec2>], unit: Unit, writer: &mut impl Write) -> io::Result<()> {
+    let (min, max) = loops
+        .iter()
+        .flatten()
+        .fold((Vec2::INFINITY, Vec2::NEG_INFINITY), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        });
+    let size = max - min;
+
+    // SVG has no meters, so those pages are sized in centimeters
+    let (scale, suffix) = match unit {
+        Unit::Millimeter => (1.0, "mm"),
+        Unit::Centimeter => (1.0, "cm"),
+        Unit::Meter => (100.0, "cm"),
+        Unit::Inch => (1.0, "in"),
+    };
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}{suffix}" height="{}{suffix}" viewBox="{} {} {} {}">"#,
+        size.x * scale,
+        size.y * scale,
+        min.x,
+        -max.y,
+        size.x,
+        size.y,
+    )?;
+
+    for points in loops {
+        let mut data = String::new();
+        for (i, p) in points.iter().enumerate() {
+            let command = if i == 0 { 'M' } else { 'L' };
+            data.push_str(&format!("{command}{} {} ", p.x, -p.y));
+        }
+        data.push('Z');
+
+        writeln!(
+            writer,
+            r#" <path d="{data}" fill="none" stroke="black" stroke-width="{}" fill-rule="evenodd"/>"#,
+            size.max_element() * 0.001
+        )?;
+    }
+
+    writeln!(writer, "</svg>")
+}
+
+/// Write the loops as closed DXF polylines, in the R12 form most cutting
+/// software reads, with coordinates in scene units.
+pub fn write_dxf(loops: &[Vec<Vec2>], unit: Unit, writer: &mut impl Write) -> io::Result<()> {
+    let units = match unit {
+        Unit::Inch => 1,
+        Unit::Millimeter => 4,
+        Unit::Centimeter => 5,
+        Unit::Meter => 6,
+    };
+
+    let mut pair = |code: u32, value: &dyn std::fmt::Display| writeln!(writer, "{code}\n{value}");
+
+    pair(0, &"SECTION")?;
+    pair(2, &"HEADER")?;
+    pair(9, &"$ACADVER")?;
+    pair(1, &"AC1009")?;
+    pair(9, &"$INSUNITS")?;
+    pair(70, &units)?;
+    pair(0, &"ENDSEC")?;
+
+    pair(0, &"SECTION")?;
+    pair(2, &"ENTITIES")?;
+
+    for points in loops {
+        pair(0, &"POLYLINE")?;
+        pair(8, &"0")?;
+        pair(66, &1)?;
+        // Closed
+        pair(70, &1)?;
+
+        for p in points {
+            pair(0, &"VERTEX")?;
+            pair(8, &"0")?;
+            pair(10, &p.x)?;
+            pair(20, &p.y)?;
+            pair(30, &0.0)?;
+        }
+
+        pair(0, &"SEQEND")?;
+        pair(8, &"0")?;
+    }
+
+    pair(0, &"ENDSEC")?;
+    pair(0, &"EOF")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::{box_scene, node};
+
+    fn square() -> Vec<Vec<Vec2>> {
+        vec![vec![
+            Vec2::new(1.0, 2.0),
+            Vec2::new(3.0, 2.0),
+            Vec2::new(3.0, 3.0),
+            Vec2::new(1.0, 3.0),
+        ]]
+    }
+
+    #[test]
+    fn svg_is_sized_in_units() {
+        let mut bytes = Vec::new();
+        write_svg(&square(), Unit::Meter, &mut bytes).unwrap();
+        let svg = String::from_utf8(bytes).unwrap();
+
+        assert!(svg.contains(r#"width="200cm" height="100cm" viewBox="1 -3 2 1""#));
+        assert!(svg.contains(r#"d="M1 -2 L3 -2 L3 -3 L1 -3 Z""#));
+    }
+
+    #[test]
+    fn dxf_has_closed_polylines() {
+        let mut bytes = Vec::new();
+        write_dxf(&square(), Unit::Millimeter, &mut bytes).unwrap();
+        let dxf = String::from_utf8(bytes).unwrap();
+
+        assert!(dxf.contains("$INSUNITS\n70\n4\n"));
+        assert!(dxf.contains("POLYLINE\n8\n0\n66\n1\n70\n1\n"));
+        assert_eq!(dxf.matches("VERTEX").count(), 4);
+        assert!(dxf.contains("10\n3\n20\n2\n30\n0\n"));
+        assert!(dxf.ends_with("ENDSEC\n0\nEOF\n"));
+    }
+
+    #[test]
+    fn section_outlines_the_box() {
+        let nodes = [node(1, box_scene().primitives()[0].clone())];
+        let path = std::env::temp_dir().join("raystacean_section_outlines_the_box.dxf");
+        let mut options = ExportOptions {
+            resolution: 32,
+            ..default()
+        };
+
+        options.section = SectionPlane {
+            axis: 1,
+            offset: 1.0,
+        };
+        let progress = ExportProgress::default();
+        let summary =
+            export_section(&nodes, &options, SectionFormat::Dxf, &path, &progress).unwrap();
+        let dxf = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(summary.contours, 1);
+        assert_eq!(dxf.matches("POLYLINE").count(), 1);
+
+        options.section.offset = 2.0;
+        let result = export_section(&nodes, &options, SectionFormat::Dxf, &path, &progress);
+        assert!(matches!(result, Err(ExportError::EmptySection)));
+    }
+}
@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+
+use super::{Smoothing, TriangleMesh};
+
+/// How far each pass moves a vertex towards the average of its neighbors.
+const LAMBDA: f32 = 0.5;
+
+/// Taubin's inflating pass, slightly stronger than the shrinking one so the
+/// two together keep the volume.
+const MU: f32 = -0.53;
+
+/// Relax the mesh's vertices towards their neighbors. Laplacian smoothing
+/// shrinks the mesh a little every pass, Taubin smoothing follows each pass
+/// with one pushing back out.
+pub fn smooth(mesh: &mut TriangleMesh, smoothing: Smoothing, iterations: u32) {
+    let factors: &[f32] = match smoothing {
+        Smoothing::Off => return,
+        Smoothing::Laplacian => &[LAMBDA],
+        Smoothing::Taubin => &[LAMBDA, MU],
+    };
+
+    let neighbors = neighbors(mesh);
+
+    for _ in 0..iterations {
+        for &factor in factors {
+            relax(&mut mesh.positions, &neighbors, factor);
+        }
+    }
+}
+
+fn relax(positions: &mut [Vec3], neighbors: &[Vec<u32>], factor: f32) {
+    let moved: Vec<Vec3> = positions
+        .iter()
+        .zip(neighbors)
+        .map(|(p, around)| {
+            if around.is_empty() {
+                return *p;
+            }
+
+            let average =
+                around.iter().map(|i| positions[*i as usize]).sum::<Vec3>() / around.len() as f32;
+            p + (average - p) * factor
+        })
+        .collect();
+
+    positions.copy_from_slice(&moved);
+}
+
+/// The vertices sharing an edge with each vertex.
+fn neighbors(mesh: &TriangleMesh) -> Vec<Vec<u32>> {
+    let mut neighbors = vec![Vec::new(); mesh.positions.len()];
+
+    for &[a, b, c] in &mesh.triangles {
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            neighbors[from as usize].push(to);
+            neighbors[to as usize].push(from);
+        }
+    }
+
+    for around in &mut neighbors {
+        around.sort_unstable();
+        around.dedup();
+    }
+
+    neighbors
+}
@@ -0,0 +1,94 @@
+use std::io::{self, Write};
+
+use bevy::prelude::*;
+
+use super::TriangleMesh;
+
+/// Write binary STL: an 80 byte header, the triangle count, then a normal,
+/// three vertices and an unused attribute per triangle, all little endian.
+pub fn write_binary(mesh: &TriangleMesh, writer: &mut impl Write) -> io::Result<()> {
+    let mut header = [0u8; 80];
+    let title = b"raystacean binary STL";
+    header[..title.len()].copy_from_slice(title);
+
+    writer.write_all(&header)?;
+    writer.write_all(&(mesh.triangles.len() as u32).to_le_bytes())?;
+
+    for triangle in &mesh.triangles {
+        let corners = triangle.map(|i| mesh.positions[i as usize]);
+
+        for v in std::iter::once(facet_normal(corners)).chain(corners) {
+            for component in v.to_array() {
+                writer.write_all(&component.to_le_bytes())?;
+            }
+        }
+
+        writer.write_all(&0u16.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+pub fn write_ascii(mesh: &TriangleMesh, writer: &mut impl Write) -> io::Result<()> {
+    writeln!(writer, "solid raystacean")?;
+
+    for triangle in &mesh.triangles {
+        let corners = triangle.map(|i| mesh.positions[i as usize]);
+        let n = facet_normal(corners);
+
+        writeln!(writer, "  facet normal {:e} {:e} {:e}", n.x, n.y, n.z)?;
+        writeln!(writer, "    outer loop")?;
+        for v in corners {
+            writeln!(writer, "      vertex {:e} {:e} {:e}", v.x, v.y, v.z)?;
+        }
+        writeln!(writer, "    endloop")?;
+        writeln!(writer, "  endfacet")?;
+    }
+
+    writeln!(writer, "endsolid raystacean")
+}
+
+/// Counter-clockwise winding seen from outside, zero for degenerate facets.
+fn facet_normal([a, b, c]: [Vec3; 3]) -> Vec3 {
+    (b - a).cross(c - a).normalize_or_zero()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> TriangleMesh {
+        TriangleMesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            triangles: vec![[0, 1, 2]],
+            ..default()
+        }
+    }
+
+    #[test]
+    fn binary_layout() {
+        let mut bytes = Vec::new();
+        write_binary(&triangle(), &mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), 84 + 50);
+        assert_eq!(u32::from_le_bytes(bytes[80..84].try_into().unwrap()), 1);
+
+        let float =
+            |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        // Normal, then the second vertex
+        assert_eq!([float(84), float(88), float(92)], [0.0, 0.0, 1.0]);
+        assert_eq!([float(108), float(112), float(116)], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn ascii_layout() {
+        let mut bytes = Vec::new();
+        write_ascii(&triangle(), &mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+
+        assert!(text.starts_with("solid raystacean\n"));
+        assert!(text.contains("facet normal 0e0 0e0 1e0\n"));
+        assert_eq!(text.matches("vertex").count(), 3);
+        assert!(text.trim_end().ends_with("endsolid raystacean"));
+    }
+}
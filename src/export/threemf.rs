@@ -0,0 +1,180 @@
+use std::fmt::Write as _;
+use std::io::{self, Seek, Write};
+
+use bevy::prelude::*;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use super::{TriangleMesh, Unit, display_color};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+ <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+ <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+</Types>
+"#;
+
+const RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+ <Relationship Target="/3D/3dmodel.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>
+"#;
+
+const MODEL_PATH: &str = "3D/3dmodel.model";
+
+/// Rotates the Y up scene to the Z up build plate, as 3MF's row major 3x4
+/// matrix.
+const Z_UP: &str = "1 0 0 0 0 1 0 -1 0 0 0 0";
+
+/// Write a 3MF package with an object and build item per mesh. Each
+/// triangle references a color group entry built from the face colors, which
+/// slicers read as color regions.
+pub fn write_3mf(
+    meshes: &[(String, TriangleMesh)],
+    unit: Unit,
+    writer: &mut (impl Write + Seek),
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("[Content_Types].xml", options)?;
+    zip.write_all(CONTENT_TYPES.as_bytes())?;
+
+    zip.start_file("_rels/.rels", options)?;
+    zip.write_all(RELATIONSHIPS.as_bytes())?;
+
+    zip.start_file(MODEL_PATH, options)?;
+    zip.write_all(model(meshes, unit).as_bytes())?;
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// The model part. Writing to a `String` can't fail, hence the unwraps.
+fn model(meshes: &[(String, TriangleMesh)], unit: Unit) -> String {
+    let mut palette: Vec<Vec3> = Vec::new();
+    let mut objects = String::new();
+
+    // Id 1 is the color group, objects follow
+    let mut ids = Vec::new();
+
+    for (name, mesh) in meshes {
+        // Objects need at least one triangle
+        if mesh.triangles.is_empty() {
+            continue;
+        }
+
+        let id = ids.len() + 2;
+        ids.push(id);
+
+        writeln!(
+            objects,
+            r#"  <object id="{id}" type="model" name="{}" pid="1" pindex="0">"#,
+            escape(name)
+        )
+        .unwrap();
+        objects.push_str("   <mesh>\n    <vertices>\n");
+        for p in &mesh.positions {
+            writeln!(
+                objects,
+                r#"     <vertex x="{}" y="{}" z="{}"/>"#,
+                p.x, p.y, p.z
+            )
+            .unwrap();
+        }
+        objects.push_str("    </vertices>\n    <triangles>\n");
+        for (t, [v1, v2, v3]) in mesh.triangles.iter().enumerate() {
+            let color = mesh.face_colors.get(t).copied().unwrap_or(Vec3::ONE);
+            let index = palette.iter().position(|c| *c == color).unwrap_or_else(|| {
+                palette.push(color);
+                palette.len() - 1
+            });
+
+            writeln!(
+                objects,
+                r#"     <triangle v1="{v1}" v2="{v2}" v3="{v3}" pid="1" p1="{index}"/>"#
+            )
+            .unwrap();
+        }
+        objects.push_str("    </triangles>\n   </mesh>\n  </object>\n");
+    }
+
+    // Objects with no colored faces still point at the first entry
+    if palette.is_empty() {
+        palette.push(Vec3::ONE);
+    }
+
+    let mut model = String::new();
+    writeln!(model, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        model,
+        r#"<model unit="{}" xml:lang="en-US" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02" xmlns:m="http://schemas.microsoft.com/3dmanufacturing/material/2015/02">"#,
+        unit.name()
+    )
+    .unwrap();
+    model.push_str(" <metadata name=\"Application\">raystacean</metadata>\n");
+    model.push_str(" <resources>\n  <m:colorgroup id=\"1\">\n");
+    for color in &palette {
+        writeln!(
+            model,
+            r#"   <m:color color="{}"/>"#,
+            display_color(*color).to_hex()
+        )
+        .unwrap();
+    }
+    model.push_str("  </m:colorgroup>\n");
+    model.push_str(&objects);
+    model.push_str(" </resources>\n <build>\n");
+    for id in ids {
+        writeln!(model, r#"  <item objectid="{id}" transform="{Z_UP}"/>"#).unwrap();
+    }
+    model.push_str(" </build>\n</model>\n");
+
+    model
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+
+    #[test]
+    fn package_has_units_colors_and_transform() {
+        let mesh = TriangleMesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z],
+            triangles: vec![[0, 1, 2], [0, 2, 3], [0, 3, 1]],
+            face_colors: vec![Vec3::X, Vec3::Z, Vec3::X],
+            ..default()
+        };
+
+        let mut bytes = Cursor::new(Vec::new());
+        write_3mf(&[("A & B".to_string(), mesh)], Unit::Inch, &mut bytes).unwrap();
+
+        let mut archive = zip::ZipArchive::new(bytes).unwrap();
+        for part in ["[Content_Types].xml", "_rels/.rels"] {
+            assert!(archive.by_name(part).is_ok(), "missing {part}");
+        }
+
+        let mut model = String::new();
+        archive
+            .by_name(MODEL_PATH)
+            .unwrap()
+            .read_to_string(&mut model)
+            .unwrap();
+
+        assert!(model.contains(r#"unit="inch""#));
+        assert!(model.contains(r#"name="A &amp; B""#));
+        assert!(model.contains(r##"<m:color color="#FF0000"/>"##));
+        assert!(model.contains(r##"<m:color color="#0000FF"/>"##));
+        assert_eq!(model.matches("<m:color ").count(), 2);
+        assert!(model.contains(r#"<triangle v1="0" v2="2" v3="3" pid="1" p1="1"/>"#));
+        assert!(model.contains(&format!(r#"<item objectid="2" transform="{Z_UP}"/>"#)));
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::{MeshReport, TriangleMesh};
+
+/// Triangles with less area than this, relative to their longest edge
+/// squared, are too thin to print or shade.
+const DEGENERATE_RATIO: f32 = 1e-6;
+
+/// Crossings closer than this to an edge or corner, in barycentric terms,
+/// are taken as touching rather than passing through.
+const INTERSECTION_EPSILON: f32 = 1e-5;
+
+/// Check the mesh for problems that trip up printers and other tools.
+pub fn check(mesh: &TriangleMesh) -> MeshReport {
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+    for &[a, b, c] in &mesh.triangles {
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            *edges.entry((from.min(to), from.max(to))).or_default() += 1;
+        }
+    }
+
+    MeshReport {
+        triangles: mesh.triangles.len(),
+        boundary_edges: edges.values().filter(|count| **count == 1).count(),
+        non_manifold_edges: edges.values().filter(|count| **count > 2).count(),
+        non_manifold_vertices: non_manifold_vertices(mesh),
+        degenerate_triangles: mesh
+            .triangles
+            .iter()
+            .filter(|t| is_degenerate(mesh, **t))
+            .count(),
+        self_intersections: self_intersections(mesh),
+    }
+}
+
+fn corners(mesh: &TriangleMesh, triangle: [u32; 3]) -> [Vec3; 3] {
+    triangle.map(|i| mesh.positions[i as usize])
+}
+
+fn is_degenerate(mesh: &TriangleMesh, [a, b, c]: [u32; 3]) -> bool {
+    if a == b || b == c || c == a {
+        return true;
+    }
+
+    let [p, q, r] = corners(mesh, [a, b, c]);
+    let longest = [q - p, r - q, p - r]
+        .iter()
+        .map(|e| e.length_squared())
+        .fold(0.0, f32::max);
+
+    (q - p).cross(r - p).length() <= DEGENERATE_RATIO * longest
+}
+
+/// Vertices where surfaces meet at a point, found as vertices whose
+/// triangles don't form a single fan joined by edges.
+fn non_manifold_vertices(mesh: &TriangleMesh) -> usize {
+    let mut around = vec![Vec::new(); mesh.positions.len()];
+    for (t, triangle) in mesh.triangles.iter().enumerate() {
+        for &i in triangle {
+            around[i as usize].push(t);
+        }
+    }
+
+    around
+        .iter()
+        .enumerate()
+        .filter(|(v, fan)| {
+            if fan.is_empty() {
+                return false;
+            }
+
+            // Walk the fan from its first triangle across shared edges
+            let mut reached = vec![false; fan.len()];
+            let mut stack = vec![0];
+            reached[0] = true;
+
+            while let Some(k) = stack.pop() {
+                let triangle = mesh.triangles[fan[k]];
+                for (j, &other) in fan.iter().enumerate() {
+                    let shares_edge = mesh.triangles[other]
+                        .iter()
+                        .any(|i| *i as usize != *v && triangle.contains(i));
+
+                    if !reached[j] && shares_edge {
+                        reached[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+
+            reached.contains(&false)
+        })
+        .count()
+}
+
+/// Pairs of triangles that pass through each other, found by bucketing
+/// triangles into a grid about the size of one and testing within buckets.
+/// Neighboring triangles, which share a vertex, are skipped.
+fn self_intersections(mesh: &TriangleMesh) -> usize {
+    if mesh.triangles.is_empty() {
+        return 0;
+    }
+
+    let bounds: Vec<(Vec3, Vec3)> = mesh
+        .triangles
+        .iter()
+        .map(|t| {
+            let [p, q, r] = corners(mesh, *t);
+            (p.min(q).min(r), p.max(q).max(r))
+        })
+        .collect();
+
+    let cell_size = bounds
+        .iter()
+        .map(|(min, max)| (*max - *min).max_element())
+        .sum::<f32>()
+        / bounds.len() as f32;
+    let cell_size = cell_size.max(f32::EPSILON);
+
+    let cell_range = |(min, max): (Vec3, Vec3)| {
+        (
+            (min / cell_size).floor().as_ivec3(),
+            (max / cell_size).floor().as_ivec3(),
+        )
+    };
+
+    let mut cells: HashMap<IVec3, Vec<usize>> = HashMap::new();
+    for (t, b) in bounds.iter().enumerate() {
+        let (low, high) = cell_range(*b);
+        for z in low.z..=high.z {
+            for y in low.y..=high.y {
+                for x in low.x..=high.x {
+                    cells.entry(IVec3::new(x, y, z)).or_default().push(t);
+                }
+            }
+        }
+    }
+
+    let mut count = 0;
+
+    for (cell, members) in &cells {
+        for (k, &i) in members.iter().enumerate() {
+            for &j in &members[k + 1..] {
+                let (a, b) = (mesh.triangles[i], mesh.triangles[j]);
+                if a.iter().any(|v| b.contains(v)) {
+                    continue;
+                }
+
+                // Pairs sharing several cells are tested in the first only
+                let (low_a, _) = cell_range(bounds[i]);
+                let (low_b, _) = cell_range(bounds[j]);
+                if low_a.max(low_b) != *cell {
+                    continue;
+                }
+
+                let (a, b) = (corners(mesh, a), corners(mesh, b));
+                if (0..3).any(|e| edge_crosses(a[e], a[(e + 1) % 3], b))
+                    || (0..3).any(|e| edge_crosses(b[e], b[(e + 1) % 3], a))
+                {
+                    count += 1;
+                }
+            }
+        }
+    }
+
+    count
+}
+
+/// Whether the segment from `start` to `end` passes through the inside of
+/// the triangle. Segments lying in its plane never do.
+fn edge_crosses(start: Vec3, end: Vec3, [p, q, r]: [Vec3; 3]) -> bool {
+    let direction = end - start;
+    let (e1, e2) = (q - p, r - p);
+
+    let h = direction.cross(e2);
+    let det = e1.dot(h);
+    if det.abs() <= f32::EPSILON * e1.length() * e2.length() * direction.length() {
+        return false;
+    }
+
+    let s = start - p;
+    let u = s.dot(h) / det;
+    let qv = s.cross(e1);
+    let v = direction.dot(qv) / det;
+    let t = e2.dot(qv) / det;
+
+    let inside = INTERSECTION_EPSILON..1.0 - INTERSECTION_EPSILON;
+    inside.contains(&t)
+        && u > INTERSECTION_EPSILON
+        && v > INTERSECTION_EPSILON
+        && inside.contains(&(u + v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron(offset: Vec3) -> TriangleMesh {
+        TriangleMesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z]
+                .into_iter()
+                .map(|p| p + offset)
+                .collect(),
+            triangles: vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]],
+            ..default()
+        }
+    }
+
+    #[test]
+    fn closed_mesh_is_clean() {
+        let report = check(&tetrahedron(Vec3::ZERO));
+
+        assert!(report.is_watertight() && report.is_manifold());
+        assert_eq!(report.self_intersections, 0);
+        assert_eq!(report.degenerate_triangles, 0);
+    }
+
+    #[test]
+    fn finds_holes_and_slivers() {
+        let mut mesh = tetrahedron(Vec3::ZERO);
+        mesh.triangles.pop();
+        mesh.positions.push(Vec3::new(2.0, 0.0, 0.0));
+        mesh.triangles.push([0, 1, 4]);
+
+        let report = check(&mesh);
+
+        assert!(!report.is_watertight());
+        assert_eq!(report.degenerate_triangles, 1);
+    }
+
+    #[test]
+    fn finds_pinched_vertices() {
+        // Two tetrahedra sharing only a corner
+        let mut mesh = tetrahedron(Vec3::ZERO);
+        let mut other = tetrahedron(Vec3::ZERO);
+        for p in &mut other.positions {
+            *p = -*p;
+        }
+        other.triangles = other
+            .triangles
+            .iter()
+            .map(|[a, b, c]| [*a, *c, *b])
+            .collect();
+
+        let offset = mesh.positions.len() as u32 - 1;
+        mesh.positions.extend(&other.positions[1..]);
+        mesh.triangles.extend(
+            other
+                .triangles
+                .iter()
+                .map(|t| t.map(|i| if i == 0 { 0 } else { i + offset })),
+        );
+
+        let report = check(&mesh);
+
+        assert!(report.is_watertight());
+        assert!(!report.is_manifold());
+        assert_eq!(report.non_manifold_vertices, 1);
+    }
+
+    #[test]
+    fn finds_overlapping_solids() {
+        let mut mesh = tetrahedron(Vec3::ZERO);
+        mesh.append(tetrahedron(Vec3::splat(0.2)));
+
+        assert!(check(&mesh).self_intersections > 0);
+        assert_eq!(check(&tetrahedron(Vec3::ZERO)).self_intersections, 0);
+    }
+}
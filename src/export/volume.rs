@@ -0,0 +1,369 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use bevy::color::ColorToPacked;
+use bevy::math::bounding::BoundingVolume;
+use bevy::prelude::*;
+use serde_json::json;
+
+use super::grid::{self, SampledGrid};
+use super::{
+    ExportError, ExportOptions, ExportProgress, MAX_RESOLUTION, MIN_RESOLUTION, display_color,
+    layer_stack, scoped_scene, shows_color,
+};
+use crate::{bounds, geometry};
+
+/// MagicaVoxel models are at most this many voxels along each axis.
+pub const VOX_MAX_SIZE: u32 = 256;
+
+const VOX_VERSION: u32 = 150;
+
+/// Formats holding samples of the field rather than a mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeFormat {
+    /// The sampled distance field as float32, with a JSON header.
+    Raw,
+    /// MagicaVoxel voxels.
+    Vox,
+    /// A zip of black and white layer images for resin printers.
+    LayerStack,
+}
+
+impl VolumeFormat {
+    pub fn label(self) -> &'static str {
+        match self {
+            VolumeFormat::Raw => "Raw volume",
+            VolumeFormat::Vox => "MagicaVoxel",
+            VolumeFormat::LayerStack => "Layer images (zip)",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            VolumeFormat::Raw => "raw",
+            VolumeFormat::Vox => "vox",
+            VolumeFormat::LayerStack => "zip",
+        }
+    }
+}
+
+/// Layer heights and pixel sizes a layer stack can be sliced at, in world
+/// units.
+pub const LAYER_SIZE_RANGE: RangeInclusive<f32> = 0.001..=10.0;
+
+/// How layer image stacks are sliced, in world units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerSlicing {
+    pub layer_height: f32,
+    /// Width and depth covered by each pixel.
+    pub pixel_size: f32,
+}
+
+impl Default for LayerSlicing {
+    fn default() -> Self {
+        Self {
+            layer_height: 0.05,
+            pixel_size: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VolumeSummary {
+    /// Samples along each axis, or pixels and layers for layer stacks.
+    pub dims: UVec3,
+}
+
+/// Sample the whole scene's distance field and write it to `path`.
+/// Cancelling leaves no file behind.
+pub fn export_volume(
+    nodes: &[geometry::SceneNode],
+    options: &ExportOptions,
+    format: VolumeFormat,
+    path: &Path,
+    progress: &ExportProgress,
+) -> Result<VolumeSummary, ExportError> {
+    let scene = scoped_scene(nodes, options);
+    let bounds = bounds::compute(&scene).ok_or(ExportError::EmptyScene)?;
+
+    let size = Vec3::from(bounds.max - bounds.min);
+    let field = progress.guard(|p| scene.map_geometry(p).dist);
+
+    let dims = match format {
+        VolumeFormat::LayerStack => {
+            // Layers are written as they're sampled, so a cancelled stack
+            // is removed rather than left cut short
+            let mut writer = BufWriter::new(File::create(path)?);
+            let stack = layer_stack::write_layer_stack(
+                field,
+                bounds,
+                options.layers,
+                progress,
+                &mut writer,
+            )?;
+            writer.flush()?;
+            drop(writer);
+
+            if progress.is_cancelled() {
+                std::fs::remove_file(path)?;
+                return Err(ExportError::Cancelled);
+            }
+            UVec3::new(stack.width, stack.layers, stack.height)
+        }
+        VolumeFormat::Vox => {
+            // A voxel centered on each sample, filling the bounds
+            let resolution = options.resolution.clamp(MIN_RESOLUTION, VOX_MAX_SIZE);
+            let cell_size = size.max_element() / resolution as f32;
+            let dims = (size / cell_size)
+                .round()
+                .as_uvec3()
+                .clamp(UVec3::ONE, UVec3::splat(resolution));
+            let origin = Vec3::from(bounds.min) + cell_size * 0.5;
+            progress.stage(0.0..0.8);
+            let grid = grid::SampledGrid::sample_points(field, origin, cell_size, dims, progress);
+            progress.check()?;
+
+            // Colors past the palette's 255 entries take the first
+            let mut palette: Vec<Vec3> = Vec::new();
+            for color in scene.primitives().iter().filter(|p| shows_color(p)) {
+                let color = Vec3::from(color.color);
+                if !palette.contains(&color) && palette.len() < 255 {
+                    palette.push(color);
+                }
+            }
+
+            let colors = |p| {
+                scene
+                    .nearest_primitive(p, shows_color)
+                    .and_then(|b| palette.iter().position(|c| *c == Vec3::from(b.color)))
+                    .map_or(1, |i| i as u8 + 1)
+            };
+
+            let mut writer = BufWriter::new(File::create(path)?);
+            write_vox(&grid, &palette, colors, &mut writer)?;
+            writer.flush()?;
+            grid.dims
+        }
+        VolumeFormat::Raw => {
+            let resolution = options.resolution.clamp(MIN_RESOLUTION, MAX_RESOLUTION);
+            let cell_size = size.max_element() / resolution as f32;
+
+            // A cell of margin so the surface is inside the volume
+            let region = bounds.grow(Vec3A::splat(cell_size));
+            progress.stage(0.0..0.8);
+            let grid = grid::SampledGrid::sample(field, region, cell_size, progress);
+            progress.check()?;
+
+            let header_path = path.with_extension("json");
+            let data_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut writer = BufWriter::new(File::create(path)?);
+            let mut header = BufWriter::new(File::create(&header_path)?);
+
+            write_raw(&grid, &data_name, &mut writer, &mut header)?;
+            writer.flush()?;
+            header.flush()?;
+            grid.dims
+        }
+    };
+
+    progress.set(1.0);
+
+    Ok(VolumeSummary { dims })
+}
+
+/// Write the samples as little endian float32, x fastest then y then z, to
+/// `data`, described by a JSON header written to `header`. Positions are in
+/// world units with Y up.
+pub fn write_raw(
+    grid: &SampledGrid,
+    data_name: &str,
+    data: &mut impl Write,
+    header: &mut impl Write,
+) -> io::Result<()> {
+    for value in &grid.values {
+        data.write_all(&value.to_le_bytes())?;
+    }
+
+    let document = json!({
+        "data": data_name,
+        "type": "float32",
+        "byte_order": "little",
+        "order": "xyz",
+        "origin": grid.origin.to_array(),
+        "spacing": grid.cell_size,
+        "dimensions": grid.dims.to_array(),
+    });
+
+    serde_json::to_writer_pretty(&mut *header, &document)?;
+    writeln!(header)
+}
+
+/// Write a MagicaVoxel model with a voxel for each sample inside the
+/// surface. `colors` gives each voxel's palette index, from 1, and
+/// `palette` the colors themselves. MagicaVoxel is Z up, so the model is
+/// turned to match.
+pub fn write_vox(
+    grid: &SampledGrid,
+    palette: &[Vec3],
+    colors: impl Fn(Vec3) -> u8,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let size = [grid.dims.x, grid.dims.z, grid.dims.y];
+
+    let mut voxels = Vec::new();
+    for z in 0..grid.dims.z {
+        for y in 0..grid.dims.y {
+            for x in 0..grid.dims.x {
+                let point = UVec3::new(x, y, z);
+                if grid.value(point) < 0.0 {
+                    let color = colors(grid.position(point));
+                    voxels.extend([x, grid.dims.z - 1 - z, y].map(|i| i as u8));
+                    voxels.push(color);
+                }
+            }
+        }
+    }
+
+    let mut size_chunk = Vec::new();
+    for axis in size {
+        size_chunk.extend(axis.to_le_bytes());
+    }
+
+    let mut xyzi_chunk = ((voxels.len() / 4) as u32).to_le_bytes().to_vec();
+    xyzi_chunk.extend(voxels);
+
+    // Entry i is palette index i + 1, unused entries are left black
+    let mut rgba_chunk = vec![0; 256 * 4];
+    for (entry, color) in rgba_chunk.chunks_mut(4).zip(palette) {
+        entry.copy_from_slice(&display_color(*color).to_u8_array());
+    }
+
+    let mut children = Vec::new();
+    for (id, content) in [
+        (b"SIZE", size_chunk),
+        (b"XYZI", xyzi_chunk),
+        (b"RGBA", rgba_chunk),
+    ] {
+        chunk(&mut children, id, &content, &[])?;
+    }
+
+    writer.write_all(b"VOX ")?;
+    writer.write_all(&VOX_VERSION.to_le_bytes())?;
+    chunk(writer, b"MAIN", &[], &children)
+}
+
+fn chunk(writer: &mut impl Write, id: &[u8; 4], content: &[u8], children: &[u8]) -> io::Result<()> {
+    writer.write_all(id)?;
+    writer.write_all(&(content.len() as u32).to_le_bytes())?;
+    writer.write_all(&(children.len() as u32).to_le_bytes())?;
+    writer.write_all(content)?;
+    writer.write_all(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::export::tests::{box_scene, node};
+
+    fn grid() -> SampledGrid {
+        // Inside only at the sample with the highest x, y and z
+        SampledGrid {
+            origin: Vec3::new(-1.0, 0.0, 2.0),
+            cell_size: 0.5,
+            dims: UVec3::new(2, 3, 4),
+            values: (0..24).map(|i| if i == 23 { -1.0 } else { 1.0 }).collect(),
+        }
+    }
+
+    #[test]
+    fn raw_header_describes_the_data() {
+        let (mut data, mut header) = (Vec::new(), Vec::new());
+        write_raw(&grid(), "scene.raw", &mut data, &mut header).unwrap();
+
+        assert_eq!(data.len(), 24 * 4);
+        assert_eq!(&data[23 * 4..], (-1.0f32).to_le_bytes());
+
+        let header: Value = serde_json::from_slice(&header).unwrap();
+        assert_eq!(header["dimensions"], json!([2, 3, 4]));
+        assert_eq!(header["origin"], json!([-1.0, 0.0, 2.0]));
+        assert_eq!(header["spacing"], 0.5);
+        assert_eq!(header["data"], "scene.raw");
+    }
+
+    #[test]
+    fn vox_layout() {
+        let mut bytes = Vec::new();
+        write_vox(&grid(), &[Vec3::X], |_| 1, &mut bytes).unwrap();
+
+        assert_eq!(&bytes[0..4], b"VOX ");
+        assert_eq!(&bytes[8..12], b"MAIN");
+
+        // SIZE follows MAIN's header, Z up
+        assert_eq!(&bytes[20..24], b"SIZE");
+        let size: Vec<u32> = bytes[32..44]
+            .chunks(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(size, [2, 4, 3]);
+
+        // One voxel, with scene Y as height and scene Z flipped into depth
+        assert_eq!(&bytes[44..48], b"XYZI");
+        assert_eq!(&bytes[56..60], 1u32.to_le_bytes());
+        assert_eq!(&bytes[60..64], [1, 0, 2, 1]);
+
+        assert_eq!(&bytes[64..68], b"RGBA");
+        assert_eq!(&bytes[76..80], [255, 0, 0, 255]);
+        assert_eq!(bytes.len(), 76 + 256 * 4);
+    }
+
+    #[test]
+    fn voxels_fill_the_box() {
+        let nodes = [node(1, box_scene().primitives()[0].clone())];
+        let path = std::env::temp_dir().join("raystacean_voxels_fill_the_box.vox");
+        let options = ExportOptions {
+            resolution: 16,
+            ..default()
+        };
+
+        let summary = export_volume(
+            &nodes,
+            &options,
+            VolumeFormat::Vox,
+            &path,
+            &ExportProgress::default(),
+        )
+        .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Every voxel center is inside the box
+        assert_eq!(summary.dims, UVec3::new(16, 8, 12));
+        assert_eq!(&bytes[56..60], (16u32 * 8 * 12).to_le_bytes());
+    }
+
+    #[test]
+    fn cancelled_layer_stacks_leave_no_file() {
+        let nodes = [node(1, box_scene().primitives()[0].clone())];
+        let path = std::env::temp_dir().join("raystacean_cancelled_layer_stack.zip");
+        let progress = ExportProgress::default();
+        progress.cancel();
+
+        let result = export_volume(
+            &nodes,
+            &ExportOptions::default(),
+            VolumeFormat::LayerStack,
+            &path,
+            &progress,
+        );
+
+        assert!(matches!(result, Err(ExportError::Cancelled)));
+        assert!(!path.exists());
+    }
+}
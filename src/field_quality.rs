@@ -0,0 +1,193 @@
+use bevy::color::palettes::css::{ORANGE, RED};
+use bevy::math::bounding::{Aabb3d, BoundingVolume};
+use bevy::prelude::*;
+
+/// Samples per axis over the scene bounds.
+pub const RESOLUTION: u32 = 32;
+
+/// Slopes up to this are treated as finite difference noise.
+const TOLERANCE: f32 = 1.01;
+
+/// Spacing of the finite differences used to measure slopes.
+const H: f32 = 0.001;
+
+/// Only the steepest violations are kept for display.
+const MAX_REPORTED: usize = 512;
+
+pub struct FieldQualityPlugin;
+
+impl Plugin for FieldQualityPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(FieldQuality::default())
+            .add_systems(Update, draw_violations);
+    }
+}
+
+/// The latest analysis of the scene's distance field.
+#[derive(Resource, Debug, Default)]
+pub struct FieldQuality {
+    pub report: Option<FieldReport>,
+    /// Draw markers where the field overestimates distance.
+    pub show_violations: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldReport {
+    pub samples: usize,
+    /// The steepest slope found, a lower bound on the field's Lipschitz
+    /// constant. Raymarching is only safe when this is at most 1.
+    pub max_gradient: f32,
+    /// How many samples had a slope over 1.
+    pub violation_count: usize,
+    /// The steepest violations, steepest first.
+    pub violations: Vec<Violation>,
+    /// Spacing between samples, used to size markers.
+    pub spacing: f32,
+    /// Factor to scale march steps by so they don't overshoot the surface.
+    pub suggested_step_scale: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Violation {
+    pub point: Vec3,
+    pub gradient: f32,
+}
+
+/// Sample a distance field on a grid over `region`, measuring how steep it
+/// is at each sample. The slope is taken as a secant along the estimated
+/// gradient, which an exact distance field keeps at or below 1 even on
+/// creases where the gradient itself is undefined.
+pub fn analyze(field: impl Fn(Vec3) -> f32, region: Aabb3d, resolution: u32) -> FieldReport {
+    let min = Vec3::from(region.min);
+    let size = Vec3::from(region.max - region.min);
+    let steps = resolution.max(2);
+    let spacing = size.max_element() / (steps - 1) as f32;
+
+    let mut samples = 0;
+    let mut max_gradient: f32 = 0.0;
+    let mut violations = Vec::new();
+
+    for z in 0..steps {
+        for y in 0..steps {
+            for x in 0..steps {
+                let t = UVec3::new(x, y, z).as_vec3() / (steps - 1) as f32;
+                let p = min + size * t;
+
+                let gradient = slope(&field, p);
+                samples += 1;
+
+                if !gradient.is_finite() {
+                    continue;
+                }
+
+                max_gradient = max_gradient.max(gradient);
+
+                if gradient > TOLERANCE {
+                    violations.push(Violation { point: p, gradient });
+                }
+            }
+        }
+    }
+
+    let violation_count = violations.len();
+    violations.sort_by(|a, b| b.gradient.total_cmp(&a.gradient));
+    violations.truncate(MAX_REPORTED);
+
+    // Leave some margin, the samples may have missed the steepest spot
+    let suggested_step_scale = if max_gradient > TOLERANCE {
+        0.9 / max_gradient
+    } else {
+        1.0
+    };
+
+    FieldReport {
+        samples,
+        max_gradient,
+        violation_count,
+        violations,
+        spacing,
+        suggested_step_scale,
+    }
+}
+
+/// The region analyzed for a scene, its bounds with room around them for rays
+/// approaching the surface.
+pub fn analysis_region(bounds: Aabb3d) -> Aabb3d {
+    bounds.grow(Vec3A::splat(1.0))
+}
+
+fn slope(field: &impl Fn(Vec3) -> f32, p: Vec3) -> f32 {
+    let gradient = Vec3::new(
+        field(p + Vec3::X * H) - field(p - Vec3::X * H),
+        field(p + Vec3::Y * H) - field(p - Vec3::Y * H),
+        field(p + Vec3::Z * H) - field(p - Vec3::Z * H),
+    );
+
+    let Some(direction) = gradient.try_normalize() else {
+        return 0.0;
+    };
+
+    (field(p + direction * H) - field(p - direction * H)).abs() / (2.0 * H)
+}
+
+fn draw_violations(quality: Res<FieldQuality>, mut gizmos: Gizmos) {
+    let Some(report) = quality.report.as_ref().filter(|_| quality.show_violations) else {
+        return;
+    };
+
+    for violation in &report.violations {
+        // Redder the further over the bound
+        let t = ((violation.gradient - 1.0) / (report.max_gradient - 1.0)).clamp(0.0, 1.0);
+        let color = Color::from(ORANGE).mix(&Color::from(RED), t);
+
+        gizmos.sphere(
+            Isometry3d::from_translation(violation.point),
+            report.spacing * 0.25,
+            color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{geometry, sdf};
+
+    fn region() -> Aabb3d {
+        Aabb3d::new(Vec3::ZERO, Vec3::splat(2.0))
+    }
+
+    #[test]
+    fn exact_fields_pass() {
+        let scene = sdf::Scene::new(vec![
+            geometry::BoxGeometry {
+                scale: Vec3::new(1.0, 0.5, 0.75),
+                rounding: 0.2,
+                ..geometry::BoxGeometry::new(Vec3::ZERO, 0)
+            },
+            geometry::BoxGeometry {
+                scale: Vec3::splat(0.5),
+                blend: 0.2,
+                is_subtract: true,
+                ..geometry::BoxGeometry::new(Vec3::new(0.5, 0.5, 0.0), 1)
+            },
+        ]);
+
+        let report = analyze(|p| scene.map_geometry(p).dist, region(), 16);
+
+        assert_eq!(report.samples, 16 * 16 * 16);
+        assert_eq!(report.violation_count, 0, "{:?}", report.violations.first());
+        assert!(report.max_gradient > 0.9 && report.max_gradient <= TOLERANCE);
+        assert_eq!(report.suggested_step_scale, 1.0);
+    }
+
+    #[test]
+    fn overestimating_field_is_reported() {
+        // A sphere whose distances are twice the true distance
+        let report = analyze(|p| 2.0 * (p.length() - 1.0), region(), 8);
+
+        assert!((report.max_gradient - 2.0).abs() < 0.01);
+        assert_eq!(report.violation_count, report.samples);
+        assert!((report.suggested_step_scale - 0.45).abs() < 0.01);
+    }
+}
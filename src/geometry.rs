@@ -1,6 +1,8 @@
-use bevy::{prelude::*, render::camera::CameraProjection};
+use bevy::{
+    ecs::system::SystemParam, math::bounding::Aabb3d, prelude::*, render::camera::CameraProjection,
+};
 
-use crate::{camera, controls, events, global_id, node_id, transform_ext::CameraViewMatrix};
+use crate::{camera, controls, events, global_id, node_id, parts, transform_ext::CameraViewMatrix};
 
 pub struct GeometryPlugin;
 
@@ -10,7 +12,7 @@ impl Plugin for GeometryPlugin {
     }
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone)]
 pub struct BoxGeometry {
     pub position: Vec3,
     pub scale: Vec3,
@@ -18,11 +20,19 @@ pub struct BoxGeometry {
     pub rounding: f32,
     pub blend: f32,
     pub is_subtract: bool,
+    /// When subtracting, the cut surface takes this box's color instead of
+    /// the color of the geometry being cut.
+    pub color_cut: bool,
+    pub visible: bool,
+    /// Locked boxes can't be selected or dragged in the viewport.
+    pub locked: bool,
+    /// While any node is isolated, only isolated nodes are shown.
+    pub isolated: bool,
     pub id: node_id::NodeId,
 }
 
 impl BoxGeometry {
-    fn new(position: Vec3, id: u32) -> Self {
+    pub fn new(position: Vec3, id: u32) -> Self {
         let id = node_id::NodeId::new(id);
         BoxGeometry {
             position,
@@ -31,11 +41,15 @@ impl BoxGeometry {
             blend: 0.0,
             color: id.to_scrambled_color(),
             is_subtract: false,
+            color_cut: false,
+            visible: true,
+            locked: false,
+            isolated: false,
             id,
         }
     }
 
-    fn with_y(self, y: f32) -> Self {
+    pub fn with_y(self, y: f32) -> Self {
         Self {
             position: self.position.with_y(y),
             ..self
@@ -47,6 +61,99 @@ impl BoxGeometry {
     pub fn rounding_radius(&self) -> f32 {
         self.rounding * self.scale.x.min(self.scale.y).min(self.scale.z)
     }
+
+    /// Conservative bounds of the box's influence on the scene. Smooth
+    /// operations blend where two distances are within 6k of each other and
+    /// move the surface by at most k, so the box is grown by 7k.
+    pub fn bounds(&self) -> Aabb3d {
+        Aabb3d::new(
+            self.position,
+            self.scale.abs() + Vec3::splat(7.0 * self.blend.abs()),
+        )
+    }
+}
+
+/// A top-level node of the scene, a box or a part instance, with the
+/// primitives it contributes.
+#[derive(Debug, Clone)]
+pub struct SceneNode {
+    pub id: node_id::NodeId,
+    /// The name shown in the scene list.
+    pub name: String,
+    pub primitives: Vec<BoxGeometry>,
+}
+
+/// The primitives that make up the scene, in the order the shaders evaluate
+/// them. Part instances are expanded into copies of their definition's members.
+#[derive(SystemParam)]
+pub struct ScenePrimitives<'w, 's> {
+    boxes: Query<'w, 's, &'static BoxGeometry, Without<parts::MemberOf>>,
+    instances: Query<'w, 's, &'static parts::PartInstance>,
+    definitions: Query<'w, 's, &'static parts::PartDefinition>,
+    members_of: Query<'w, 's, &'static parts::PartMembers>,
+    members: Query<'w, 's, &'static BoxGeometry, With<parts::MemberOf>>,
+}
+
+impl ScenePrimitives<'_, '_> {
+    /// Collect the visible primitives. Hidden nodes are left out, as is
+    /// everything that isn't isolated while any node is isolated.
+    pub fn collect(&self) -> Vec<BoxGeometry> {
+        self.collect_nodes()
+            .into_iter()
+            .flat_map(|node| node.primitives)
+            .collect()
+    }
+
+    /// The visible nodes, in evaluation order.
+    pub fn collect_nodes(&self) -> Vec<SceneNode> {
+        let isolating = self.boxes.iter().any(|b| b.isolated)
+            || self.instances.iter().any(|instance| instance.isolated);
+        let shown = |visible: bool, isolated: bool| visible && (isolated || !isolating);
+
+        let mut nodes: Vec<SceneNode> = self
+            .boxes
+            .iter()
+            .filter(|b| shown(b.visible, b.isolated))
+            .map(|b| SceneNode {
+                id: b.id,
+                name: format!("Box {}", b.id),
+                primitives: vec![b.clone()],
+            })
+            .collect();
+
+        for instance in &self.instances {
+            if !shown(instance.visible, instance.isolated) {
+                continue;
+            }
+
+            let mut members: Vec<&BoxGeometry> = self
+                .members_of
+                .get(instance.definition)
+                .map(|members| self.members.iter_many(members.iter()).collect())
+                .unwrap_or_default();
+
+            members.retain(|m| m.visible);
+
+            members.sort_by_key(|m| m.id);
+
+            let part = self
+                .definitions
+                .get(instance.definition)
+                .map(|definition| definition.name.as_str())
+                .unwrap_or("Missing part");
+
+            nodes.push(SceneNode {
+                id: instance.id,
+                name: format!("{part} ({})", instance.id),
+                primitives: members.into_iter().map(|m| instance.place(m)).collect(),
+            });
+        }
+
+        // Sorted by ID to ensure stable operation ordering seen by the shader
+        nodes.sort_by_key(|node| node.id);
+
+        nodes
+    }
 }
 
 fn place_box(
@@ -81,37 +188,63 @@ fn place_box(
     };
 }
 
+/// Fires rays from a camera through points on the screen, using the same
+/// steps as the shaders so CPU rays line up with rendered pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraRays {
+    view_to_world: Mat4,
+    clip_to_view: Mat4,
+}
+
+impl CameraRays {
+    pub fn new(projection: &Projection, camera_transform: &Transform) -> Self {
+        Self {
+            view_to_world: camera_transform.view_matrix().inverse(),
+            clip_to_view: projection.get_clip_from_view().inverse(),
+        }
+    }
+
+    /// Ray through a screen space position, with the origin in the top left.
+    pub fn through_screen(&self, screen_space_position: Vec2, screen_size: Vec2) -> Ray3d {
+        self.through_ndc((screen_space_position / screen_size) * 2.0 - Vec2::ONE)
+    }
+
+    pub fn through_ndc(&self, ndc: Vec2) -> Ray3d {
+        let ndc = Vec4::new(ndc.x, ndc.y, -1.0, 1.0);
+
+        // 2. NDC → view space
+        let view_pos_h = self.clip_to_view * ndc;
+        let view_pos = view_pos_h.xyz() / view_pos_h.w;
+
+        // Ray in view space
+        let ray_origin_view = Vec4::default().with_w(1.0);
+        let ray_dir_view = view_pos.normalize().extend(0.0);
+
+        // 4. View -> world
+        let ray_origin_world = (self.view_to_world * ray_origin_view).xyz();
+        let ray_dir_world = (self.view_to_world * ray_dir_view).xyz();
+
+        Ray3d::new(
+            ray_origin_world,
+            Dir3::new(ray_dir_world).expect("camera ray direction should be valid"),
+        )
+    }
+}
+
 // Given a 2d screen space position, fire a ray into the scene along the camera
 // axis and find the intersection with the ground plane (Y=0). Returns `None`
 // if there is no intersection (e.g.) ray direction points away from ground plane.
-fn cast_ray_at_ground_in_scene(
+pub fn cast_ray_at_ground_in_scene(
     screen_space_position: Vec2,
     projection: &Projection,
     camera_transform: &Transform,
     window: &Window,
 ) -> Option<Vec3> {
-    let screen_size = window.size();
-
-    let ndc = (screen_space_position / screen_size) * 2.0 - Vec2::ONE;
-    let ndc = Vec4::new(ndc.x, ndc.y, -1.0, 1.0);
-
-    // 2. NDC → view space
-    let view_pos_h = projection.get_clip_from_view().inverse() * ndc;
-    let view_pos = view_pos_h.xyz() / view_pos_h.w;
-
-    // Ray in view space
-    let ray_origin_view = Vec4::default().with_w(1.0);
-    let ray_dir_view = view_pos.normalize().extend(0.0);
-
-    // 4. View -> world
-    let ray_origin_world = (camera_transform.view_matrix().inverse() * ray_origin_view).xyz();
-
-    let ray_dir_world = (camera_transform.view_matrix().inverse() * ray_dir_view)
-        .normalize()
-        .xyz();
+    let ray = CameraRays::new(projection, camera_transform)
+        .through_screen(screen_space_position, window.size());
 
     // Solve for t value that intersects XZ plane (Y=0)
-    let t = -ray_origin_world.y / ray_dir_world.y;
+    let t = -ray.origin.y / ray.direction.y;
 
     // Intersection point is behind origin
     if t < 0.0 {
@@ -119,5 +252,5 @@ fn cast_ray_at_ground_in_scene(
     }
 
     // Substitute back into ray eqn
-    Some(ray_origin_world + ray_dir_world * t)
+    Some(ray.get_point(t))
 }
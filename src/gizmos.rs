@@ -1,4 +1,4 @@
-use crate::{events, geometry, layers, selection};
+use crate::{events, geometry, layers, parts, selection};
 use bevy::color::palettes::css::{BLUE, GREEN, RED};
 use bevy::ecs::relationship::RelatedSpawnerCommands;
 use bevy::prelude::*;
@@ -22,13 +22,24 @@ impl Plugin for GizmosPlugin {
 // Draw a coordinate system for the selected box
 fn draw_coordinate_system(
     selected: Query<&geometry::BoxGeometry, With<selection::Selected>>,
+    selected_instance: Query<&parts::PartInstance, With<selection::Selected>>,
     mut origin: Query<(&mut Transform, &mut Visibility), With<Origin>>,
 ) {
     let (mut transform, mut visibility) = origin.single_mut().expect("single");
 
-    if let Ok(selected) = selected.single() {
+    // Locked nodes can't be dragged, so they get no handles
+    let position = selected
+        .single()
+        .map(|selected| (selected.position, selected.locked))
+        .or_else(|_| {
+            selected_instance
+                .single()
+                .map(|instance| (instance.position, instance.locked))
+        });
+
+    if let Ok((position, false)) = position {
         *visibility = Visibility::Visible;
-        *transform = Transform::from_translation(selected.position);
+        *transform = Transform::from_translation(position);
     } else {
         *visibility = Visibility::Hidden;
     }
@@ -40,7 +51,7 @@ fn draw_scaling_cubes(
     scaling_cube: Query<(&mut Transform, &mut Visibility, &ScalingGizmo)>,
 ) {
     for (mut transform, mut visibility, ScalingGizmo(axis)) in scaling_cube {
-        if let Ok(selected) = selected.single() {
+        if let Some(selected) = selected.single().ok().filter(|selected| !selected.locked) {
             *visibility = Visibility::Visible;
             *transform = match axis {
                 Axis::X => Transform::from_translation(
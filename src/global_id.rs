@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 
+use crate::node_id;
+
 pub struct GlobalIdPlugin;
 
 impl Plugin for GlobalIdPlugin {
@@ -18,4 +20,10 @@ impl GlobalId {
 
         id
     }
+
+    /// Make sure IDs handed out from now on come after `used`, such as
+    /// after loading a scene.
+    pub fn skip_past(&mut self, used: node_id::NodeId) {
+        self.0 = self.0.max(used.value() + 1);
+    }
 }
@@ -1,8 +1,6 @@
 
 pub const SHADER_LAYER: usize = 0;
-pub const SELECTION_LAYER: usize = 1;
 pub const GIZMOS_LAYER: usize = 2;
 
 pub const SHADER_CAMERA: isize = 0;
-pub const SELECTION_CAMERA: isize = 1;
 pub const GIZMOS_CAMERA: isize = 2;
@@ -1,13 +1,23 @@
+mod bounds;
+mod bvh;
 mod camera;
+mod cli;
 mod controls;
+mod cpu_render;
 mod events;
+mod export;
+mod field_quality;
 mod geometry;
 mod gizmos;
 mod global_id;
 mod layers;
 mod manipulation;
 mod node_id;
+mod parts;
+mod picking;
 mod rendering;
+mod scene_file;
+mod sdf;
 mod selection;
 mod transform_ext;
 mod ui;
@@ -19,13 +29,17 @@ use bevy::prelude::*;
 
 #[cfg_attr(feature = "hotpath", hotpath::main(percentiles = [99]))]
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+
     let asset_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
         .join("assets")
         .to_string_lossy()
         .to_string();
 
     App::new()
-        .add_event::<events::PixelColorUnderCursor>()
         .add_event::<events::PlaneClicked>()
         .add_event::<events::OriginDragged>()
         .add_event::<events::GeometryAdded>()
@@ -40,13 +54,18 @@ fn main() {
         })
         .add_plugins(MeshPickingPlugin)
         .add_plugins((
+            bounds::BoundsPlugin,
             camera::CameraPlugin,
             controls::ControlContextPlugin,
+            export::ExportPlugin,
+            field_quality::FieldQualityPlugin,
             geometry::GeometryPlugin,
             gizmos::GizmosPlugin,
             global_id::GlobalIdPlugin,
             manipulation::ManipulationPlugin,
+            parts::PartsPlugin,
             rendering::RenderingPlugin,
+            scene_file::SceneFilePlugin,
             selection::SelectionPlugin,
             ui::UiPlugin,
         ))
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{camera, events, geometry, selection};
+use crate::{camera, events, geometry, parts, selection};
 
 pub struct ManipulationPlugin;
 
@@ -23,6 +23,10 @@ fn apply_position_drag_to_selection(
 ) {
     for event in drag_events.read() {
         let mut geometry = selected.single_mut().expect("single");
+        if geometry.locked {
+            continue;
+        }
+
         let (camera_transform, camera) = camera.single().expect("single");
         if let Some(delta_scalar) = axis_drag_scalar(
             camera,
@@ -39,19 +43,29 @@ fn apply_position_drag_to_selection(
 fn apply_scaling_drag_to_selection(
     mut drag_events: EventReader<events::OriginDragged>,
     mut selected: Query<&mut geometry::BoxGeometry, With<selection::Selected>>,
+    mut selected_instance: Query<&mut parts::PartInstance, With<selection::Selected>>,
     camera: Query<(&GlobalTransform, &Camera), With<camera::MainCamera>>,
 ) {
     for event in drag_events.read() {
-        let mut geometry = selected.single_mut().expect("single");
+        let position = if let Ok(geometry) = selected.single_mut() {
+            if geometry.locked {
+                continue;
+            }
+            &mut geometry.into_inner().position
+        } else if let Ok(instance) = selected_instance.single_mut() {
+            if instance.locked {
+                continue;
+            }
+            &mut instance.into_inner().position
+        } else {
+            continue;
+        };
+
         let (camera_transform, camera) = camera.single().expect("single");
-        if let Some(delta_scalar) = axis_drag_scalar(
-            camera,
-            camera_transform,
-            geometry.position,
-            event.axis,
-            event.delta,
-        ) {
-            geometry.position += event.axis.normalize() * delta_scalar * 0.05;
+        if let Some(delta_scalar) =
+            axis_drag_scalar(camera, camera_transform, *position, event.axis, event.delta)
+        {
+            *position += event.axis.normalize() * delta_scalar * 0.05;
         }
     }
 }
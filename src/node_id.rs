@@ -1,7 +1,9 @@
-use bevy::prelude::*;
 use std::fmt::Display;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct NodeId(u32);
 
 impl Display for NodeId {
@@ -15,12 +17,13 @@ impl NodeId {
         Self(id)
     }
 
-    pub fn to_color(self) -> [f32; 3] {
-        let r = ((self.0 & 0xFF) as f32) / 255.0;
-        let g = (((self.0 >> 8) & 0xFF) as f32) / 255.0;
-        let b = (((self.0 >> 16) & 0xFF) as f32) / 255.0;
+    pub fn value(self) -> u32 {
+        self.0
+    }
 
-        [r, g, b]
+    /// Whether the ID is small enough to be given a color.
+    pub fn is_valid(self) -> bool {
+        self.0 < MODULUS
     }
 
     pub fn to_scrambled_color(self) -> [f32; 3] {
@@ -32,14 +35,6 @@ impl NodeId {
 
         [r, g, b]
     }
-
-    pub fn from_color(color: Vec3) -> Self {
-        let r = (color.x * 255.0) as u32;
-        let g = ((color.y * 255.0) as u32) << 8;
-        let b = ((color.z * 255.0) as u32) << 16;
-
-        Self(r | g | b)
-    }
 }
 
 const MODULUS: u32 = 1 << 24;
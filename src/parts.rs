@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+
+use crate::{camera, controls, events, geometry, global_id, node_id};
+
+pub struct PartsPlugin;
+
+impl Plugin for PartsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(place_instance);
+    }
+}
+
+/// A reusable group of primitives that can be placed in the scene many times.
+/// The primitives themselves are `BoxGeometry` entities linked to the
+/// definition with [`MemberOf`], positioned relative to the part origin.
+#[derive(Component, Debug)]
+pub struct PartDefinition {
+    pub name: String,
+}
+
+/// Links a primitive to the part definition it belongs to. Member primitives
+/// are never rendered directly, only through the instances of their part.
+#[derive(Component, Debug)]
+#[relationship(relationship_target = PartMembers)]
+pub struct MemberOf(pub Entity);
+
+#[derive(Component, Debug)]
+#[relationship_target(relationship = MemberOf, linked_spawn)]
+pub struct PartMembers(Vec<Entity>);
+
+/// A placement of a part definition in the scene. Editing the definition's
+/// members updates every instance.
+#[derive(Component, Debug)]
+pub struct PartInstance {
+    pub definition: Entity,
+    pub position: Vec3,
+    pub visible: bool,
+    /// Locked instances can't be selected or dragged in the viewport.
+    pub locked: bool,
+    /// While any node is isolated, only isolated nodes are shown.
+    pub isolated: bool,
+    pub id: node_id::NodeId,
+}
+
+impl PartInstance {
+    pub fn new(definition: Entity, position: Vec3, id: u32) -> Self {
+        Self {
+            definition,
+            position,
+            visible: true,
+            locked: false,
+            isolated: false,
+            id: node_id::NodeId::new(id),
+        }
+    }
+
+    /// The instance's copy of a member primitive. The copy takes the instance
+    /// ID and lock so that picking resolves to the instance, not the primitive.
+    pub fn place(&self, member: &geometry::BoxGeometry) -> geometry::BoxGeometry {
+        geometry::BoxGeometry {
+            position: member.position + self.position,
+            locked: self.locked,
+            id: self.id,
+            ..member.clone()
+        }
+    }
+}
+
+/// Turn a top-level box into a part definition, replacing the box in the scene
+/// with an instance of the new part placed where the box was.
+pub fn make_part_from_box(
+    entity: Entity,
+    geometry: &mut geometry::BoxGeometry,
+    global_id: &mut global_id::GlobalId,
+    commands: &mut Commands,
+) -> Entity {
+    // Part origin sits on the ground plane below the box
+    let origin = geometry.position.with_y(0.0);
+    geometry.position -= origin;
+
+    let definition = commands
+        .spawn(PartDefinition {
+            name: format!("Part {}", geometry.id),
+        })
+        .id();
+
+    commands.entity(entity).insert(MemberOf(definition));
+
+    commands
+        .spawn(PartInstance::new(definition, origin, global_id.next()))
+        .id()
+}
+
+fn place_instance(
+    _trigger: Trigger<events::PlaneClicked>,
+    mut control_mode: ResMut<controls::ControlMode>,
+    windows: Query<&Window>,
+    camera: Query<(&Projection, &Transform), With<camera::MainCamera>>,
+    mut global_id: ResMut<global_id::GlobalId>,
+    mut commands: Commands,
+) {
+    let controls::ControlMode::PlacePart(definition) = *control_mode else {
+        return;
+    };
+
+    let window = windows.single().expect("single");
+    let (projection, transform) = camera.single().expect("single");
+
+    let Some(cursor_pos) = window.cursor_position() else {
+        return;
+    };
+
+    if let Some(hit) =
+        geometry::cast_ray_at_ground_in_scene(cursor_pos, projection, transform, window)
+    {
+        let entity_id = commands
+            .spawn(PartInstance::new(definition, hit, global_id.next()))
+            .id();
+
+        commands.trigger(events::GeometryAdded { entity: entity_id });
+
+        *control_mode = controls::ControlMode::Select;
+    };
+}
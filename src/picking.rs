@@ -0,0 +1,145 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{camera, geometry, node_id, sdf};
+
+const MAX_STEPS: i32 = 256;
+const HIT_THRESHOLD: f32 = 0.001;
+const MAX_DISTANCE: f32 = 200.0;
+
+/// Where a ray first meets the scene, including the ground plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickHit {
+    pub point: Vec3,
+    pub normal: Vec3,
+    /// Distance along the ray to the hit.
+    pub distance: f32,
+    /// The node whose surface was hit, `None` for the ground.
+    pub node: Option<node_id::NodeId>,
+}
+
+/// Synchronous picking against the scene's distance field, usable from any
+/// system.
+#[derive(SystemParam)]
+pub struct ScenePicker<'w, 's> {
+    primitives: geometry::ScenePrimitives<'w, 's>,
+    windows: Query<'w, 's, &'static Window>,
+    camera: Query<'w, 's, (&'static Projection, &'static Transform), With<camera::MainCamera>>,
+}
+
+impl ScenePicker<'_, '_> {
+    pub fn pick(&self, ray: Ray3d) -> Option<PickHit> {
+        pick(&sdf::Scene::new(self.primitives.collect()), ray)
+    }
+
+    /// Pick through the cursor, `None` when the cursor is outside the window
+    /// or the ray misses.
+    pub fn pick_under_cursor(&self) -> Option<PickHit> {
+        let window = self.windows.single().expect("single");
+        let (projection, transform) = self.camera.single().expect("single");
+
+        let cursor_pos = window.cursor_position()?;
+        let ray = geometry::CameraRays::new(projection, transform)
+            .through_screen(cursor_pos, window.size());
+
+        self.pick(ray)
+    }
+}
+
+/// March a ray through the scene and report the first surface it meets.
+pub fn pick(scene: &sdf::Scene, ray: Ray3d) -> Option<PickHit> {
+    let mut distance = 0.0;
+
+    for _ in 0..MAX_STEPS {
+        let point = ray.get_point(distance);
+        let result = scene.map(point);
+
+        if result.dist < HIT_THRESHOLD {
+            return Some(PickHit {
+                point,
+                normal: scene.normal(point),
+                distance,
+                node: owner(scene, point),
+            });
+        }
+
+        distance += result.dist;
+
+        if distance > MAX_DISTANCE {
+            break;
+        }
+    }
+
+    None
+}
+
+/// The node owning the surface at `p`, `None` for the ground.
+fn owner(scene: &sdf::Scene, p: Vec3) -> Option<node_id::NodeId> {
+    if sdf::sd_ground(p).dist < scene.map_geometry(p).dist {
+        return None;
+    }
+
+    scene.nearest_primitive(p, |_| true).map(|b| b.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node_id::NodeId;
+
+    fn unit_box(id: u32, position: Vec3) -> geometry::BoxGeometry {
+        geometry::BoxGeometry {
+            scale: Vec3::ONE,
+            ..geometry::BoxGeometry::new(position, id)
+        }
+    }
+
+    fn down_at(x: f32, z: f32) -> Ray3d {
+        Ray3d::new(Vec3::new(x, 10.0, z), Dir3::NEG_Y)
+    }
+
+    #[test]
+    fn picks_top_face_of_box() {
+        let scene = sdf::Scene::new(vec![unit_box(3, Vec3::new(0.0, 1.0, 0.0))]);
+
+        let hit = pick(&scene, down_at(0.2, -0.3)).unwrap();
+
+        assert!(hit.point.distance(Vec3::new(0.2, 2.0, -0.3)) < 0.01);
+        assert!(hit.normal.dot(Vec3::Y) > 0.999);
+        assert!((hit.distance - 8.0).abs() < 0.01);
+        assert_eq!(hit.node, Some(NodeId::new(3)));
+    }
+
+    #[test]
+    fn misses_geometry_and_hits_ground() {
+        let scene = sdf::Scene::new(vec![unit_box(0, Vec3::new(0.0, 1.0, 0.0))]);
+
+        let hit = pick(&scene, down_at(3.0, 0.0)).unwrap();
+
+        assert!(hit.point.y.abs() < 0.01);
+        assert_eq!(hit.node, None);
+
+        let sky = Ray3d::new(Vec3::new(3.0, 1.0, 0.0), Dir3::Y);
+        assert!(pick(&scene, sky).is_none());
+    }
+
+    #[test]
+    fn cut_face_belongs_to_cutter() {
+        let base = unit_box(0, Vec3::new(0.0, 1.0, 0.0));
+        let cutter = geometry::BoxGeometry {
+            scale: Vec3::splat(0.5),
+            is_subtract: true,
+            ..unit_box(1, Vec3::new(0.0, 2.0, 0.0))
+        };
+        let scene = sdf::Scene::new(vec![base, cutter]);
+
+        // Down into the pocket, onto the cutter's floor
+        let hit = pick(&scene, down_at(0.0, 0.0)).unwrap();
+        assert!((hit.point.y - 1.5).abs() < 0.01);
+        assert_eq!(hit.node, Some(NodeId::new(1)));
+
+        // Beside the pocket, onto the base's top face
+        let hit = pick(&scene, down_at(0.8, 0.0)).unwrap();
+        assert!((hit.point.y - 2.0).abs() < 0.01);
+        assert_eq!(hit.node, Some(NodeId::new(0)));
+    }
+}
@@ -1,28 +1,28 @@
-use bevy::asset::RenderAssetUsages;
+use bevy::asset::weak_handle;
 use bevy::prelude::*;
-use bevy::render::gpu_readback::{Readback, ReadbackComplete};
-use bevy::render::render_resource::{
-    AsBindGroup, BufferUsages, Extent3d, ShaderRef, ShaderType, TextureDimension, TextureFormat,
-    TextureUsages,
-};
+use bevy::render::render_resource::{AsBindGroup, ShaderRef};
 use bevy::render::storage::ShaderStorageBuffer;
 use bevy::render::view::RenderLayers;
 use bevy::window::WindowResized;
 
-use crate::layers::SHADER_CAMERA;
 use crate::events;
-use crate::{geometry, layers};
+use crate::layers::SHADER_CAMERA;
+use crate::{bvh, geometry, layers};
 
 pub struct RenderingPlugin;
 
 impl Plugin for RenderingPlugin {
     fn build(&self, app: &mut App) {
+        app.world_mut()
+            .resource_mut::<Assets<Shader>>()
+            .insert(&GPU_TYPES_SHADER, gpu_types_shader());
+
         app.add_plugins(MaterialPlugin::<LitMaterial>::default())
-            .add_plugins(MaterialPlugin::<SelectionMaterial>::default())
             .add_systems(Startup, setup)
+            .insert_resource(StepScale::default())
             .add_systems(
                 Update,
-                (boxes_to_gpu, cursor_position, window_resize_system),
+                (boxes_to_gpu, update_step_scale, window_resize_system),
             );
     }
 }
@@ -30,67 +30,37 @@ impl Plugin for RenderingPlugin {
 #[derive(Component)]
 struct RenderingPlane;
 
+/// Scales each raymarching step. Fields that overestimate distance need a
+/// scale below 1 so rays don't step through the surface.
+#[derive(Resource, Debug, PartialEq)]
+pub struct StepScale(pub f32);
+
+impl Default for StepScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
 fn setup(
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
     mut commands: Commands,
-    mut images: ResMut<Assets<Image>>,
     mut lit_material: ResMut<Assets<LitMaterial>>,
-    mut selection_material: ResMut<Assets<SelectionMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
     window: Single<&Window>,
 ) {
-    let size = Extent3d {
-        width: window.width().round() as u32,
-        height: window.height().round() as u32,
-        ..default()
-    };
-
-    let mut image = Image::new_fill(
-        size,
-        TextureDimension::D2,
-        &[0, 0, 0, 0],
-        TextureFormat::Bgra8UnormSrgb,
-        RenderAssetUsages::default(),
-    );
-
-    image.texture_descriptor.usage =
-        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
-
-    let image_handle = images.add(image);
-
     let primatives = buffers.add(ShaderStorageBuffer::default());
-
-    let selection_buffer = vec![0.0; 3];
-    let mut selection_buffer = ShaderStorageBuffer::from(selection_buffer);
-    selection_buffer.buffer_description.usage |= BufferUsages::COPY_SRC;
-
-    let selection = buffers.add(selection_buffer);
+    let bvh = buffers.add(ShaderStorageBuffer::default());
 
     let lit_material_handle = lit_material.add(LitMaterial {
         view_to_world: Mat4::default(),
         clip_to_view: Mat4::default(),
         primatives: primatives.clone(),
+        bvh: bvh.clone(),
+        step_scale: 1.0,
     });
 
-    let selection_material_handle = selection_material.add(SelectionMaterial {
-        view_to_world: Mat4::default(),
-        clip_to_view: Mat4::default(),
-        primatives: primatives.clone(),
-        selection: selection.clone(),
-        cursor_position: Vec2::default(),
-    });
-
-    commands.spawn(Readback::buffer(selection)).observe(
-        |trigger: Trigger<ReadbackComplete>, mut ev: EventWriter<events::PixelColorUnderCursor>| {
-            let data: Vec<f32> = trigger.event().to_shader_type();
-
-            ev.write(events::PixelColorUnderCursor::new(Vec3::new(
-                data[0], data[1], data[2],
-            )));
-        },
-    );
-
     commands.insert_resource(PrimativesBufferHandle(primatives));
+    commands.insert_resource(BvhBufferHandle(bvh));
 
     let mesh = meshes.add(Mesh::from(Plane3d::new(
         Vec3::Z,
@@ -101,7 +71,7 @@ fn setup(
     commands
         .spawn((
             RenderingPlane,
-            Mesh3d(mesh.clone()),
+            Mesh3d(mesh),
             MeshMaterial3d(lit_material_handle),
             RenderLayers::layer(layers::SHADER_LAYER),
         ))
@@ -121,32 +91,6 @@ fn setup(
         }),
         RenderLayers::layer(layers::SHADER_LAYER),
     ));
-
-    // Selection pass, render to an image
-    commands
-        .spawn((
-            RenderingPlane,
-            Mesh3d(mesh),
-            MeshMaterial3d(selection_material_handle),
-            RenderLayers::layer(layers::SELECTION_LAYER),
-        ))
-        .observe(output_click_event);
-
-    commands.spawn((
-        Camera3d::default(),
-        Camera {
-            order: layers::SELECTION_CAMERA,
-            target: image_handle.clone().into(),
-            clear_color: Color::WHITE.into(),
-            ..default()
-        },
-        Transform::from_xyz(0.0, 0.0, 1.0),
-        Projection::from(OrthographicProjection {
-            scale: 1.0,
-            ..OrthographicProjection::default_3d()
-        }),
-        RenderLayers::layer(layers::SELECTION_LAYER),
-    ));
 }
 
 fn window_resize_system(
@@ -175,75 +119,152 @@ fn output_click_event(trigger: Trigger<Pointer<Click>>, mut commands: Commands)
 }
 
 fn boxes_to_gpu(
-    boxes: Query<&geometry::BoxGeometry>,
+    scene: geometry::ScenePrimitives,
     buffer_handle: Res<PrimativesBufferHandle>,
+    bvh_handle: Res<BvhBufferHandle>,
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
 ) {
+    let primitives = scene.collect();
+
+    let bounds: Vec<_> = primitives.iter().map(|b| b.bounds()).collect();
+    let bvh_data: Vec<GpuBvhNode> = bvh::build(&bounds)
+        .into_iter()
+        .map(|node| GpuBvhNode {
+            min: node.bounds.min.into(),
+            primative: node.primitive.unwrap_or(BVH_INTERNAL_NODE),
+            max: node.bounds.max.into(),
+            escape: node.escape,
+        })
+        .collect();
+
+    bvh_handle.get_mut(&mut buffers).set_data(bvh_data);
+
     let buffer = buffer_handle.get_mut(&mut buffers);
 
-    let gpu_data: Vec<GpuPrimative> = boxes
+    let gpu_data: Vec<GpuPrimative> = primitives
         .iter()
-        // Sorted by ID to ensure stable operation ordering seen by the shader
-        .sort_by::<&geometry::BoxGeometry>(|a, b| a.id.cmp(&b.id))
         .map(|b| GpuPrimative {
-            position: b.position.into(),
-            scale: b.scale.into(),
-            color: b.color,
+            position: b.position,
+            scale: b.scale,
+            color: b.color.into(),
             blend: b.blend,
             rounding_radius: b.rounding_radius(),
-            logical_color: b.id.to_color(),
             is_subtract: if b.is_subtract { 1 } else { 0 },
-            ..default()
+            flags: flags(b),
         })
         .collect();
 
     buffer.set_data(gpu_data);
 }
 
-fn cursor_position(windows: Query<&Window>, mut materials: ResMut<Assets<SelectionMaterial>>) {
-    let window = windows.single().expect("single");
-
-    let Some(cursor_pos) = window.cursor_position() else {
+fn update_step_scale(step_scale: Res<StepScale>, mut materials: ResMut<Assets<LitMaterial>>) {
+    if !step_scale.is_changed() {
         return;
-    };
+    }
 
     for (_, material) in materials.iter_mut() {
-        // Cursor position to ndc
-        material.cursor_position = Vec2::new(
-            cursor_pos.x / window.width() * 2.0 - 1.0,
-            (cursor_pos.y / window.height() * 2.0) - 1.0,
-        );
+        material.step_scale = step_scale.0;
     }
 }
 
-#[repr(C)]
-#[derive(Clone, ShaderType, Default)]
-pub struct GpuPrimative {
-    pub position: [f32; 3],
-    pub is_subtract: u32,
-    pub scale: [f32; 3],
-    pub blend: f32,
-    pub color: [f32; 3],
-    pub rounding_radius: f32,
-    pub logical_color: [f32; 3],
-    _pad1: f32,
+fn flags(b: &geometry::BoxGeometry) -> u32 {
+    let mut flags = 0;
+
+    if b.color_cut {
+        flags |= FLAG_COLOR_CUT;
+    }
+
+    flags
 }
 
-/// Material linked to shader that displays only primative shapes, rendering
-/// each shape according to a color representation of its ID, used for color
-/// picking selection.
-#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
-pub struct SelectionMaterial {
-    #[uniform(0)]
-    pub view_to_world: Mat4,
-    #[uniform(1)]
-    pub clip_to_view: Mat4,
-    #[uniform(2)]
-    pub cursor_position: Vec2,
-    #[storage(3, read_only)]
-    pub primatives: Handle<ShaderStorageBuffer>,
-    #[storage(4)]
-    pub selection: Handle<ShaderStorageBuffer>,
+pub use gpu::{GpuBvhNode, GpuPrimative};
+
+/// `GpuPrimative::flags` bit: a subtraction colors its cut surface with the
+/// cutter's color. Mirrored in `scene.wgsl`.
+pub const FLAG_COLOR_CUT: u32 = 1;
+
+/// `GpuBvhNode::primative` value marking an internal node. Mirrored in the
+/// shaders.
+pub const BVH_INTERNAL_NODE: u32 = u32::MAX;
+
+/// Import path of the shader module declaring the GPU types below, generated
+/// from their Rust definitions so the layouts can't drift.
+const GPU_TYPES_IMPORT_PATH: &str = "rust_cad::gpu_types";
+
+const GPU_TYPES_SHADER: Handle<Shader> = weak_handle!("4f7f1c0e-9a55-4d8e-9a0b-6c2d3e1f5b71");
+
+macro_rules! wgsl_type {
+    (Vec3) => {
+        "vec3<f32>"
+    };
+    (f32) => {
+        "f32"
+    };
+    (u32) => {
+        "u32"
+    };
+}
+
+/// Declares a struct shared with the shaders along with `WGSL`, its
+/// declaration in WGSL.
+macro_rules! gpu_struct {
+    ($(#[$meta:meta])* pub struct $name:ident { $(pub $field:ident: $ty:tt,)* }) => {
+        $(#[$meta])*
+        #[repr(C)]
+        #[derive(Clone, ShaderType, Default)]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl $name {
+            pub const WGSL: &str = concat!(
+                "struct ", stringify!($name), " {\n",
+                $("    ", stringify!($field), ": ", wgsl_type!($ty), ",\n",)*
+                "}\n",
+            );
+        }
+    };
+}
+
+// `ShaderType` derives checks on each field's type as functions beside the
+// struct, which are never called, so newer compilers report them as dead
+// code. Only a module can allow that, so this one holds nothing else.
+#[allow(dead_code)]
+mod gpu {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    gpu_struct! {
+        pub struct GpuPrimative {
+            pub position: Vec3,
+            pub is_subtract: u32,
+            pub scale: Vec3,
+            pub blend: f32,
+            pub color: Vec3,
+            pub rounding_radius: f32,
+            pub flags: u32,
+        }
+    }
+
+    gpu_struct! {
+        /// A node of the primitive bounding volume hierarchy, see `bvh::BvhNode`.
+        pub struct GpuBvhNode {
+            pub min: Vec3,
+            pub primative: u32,
+            pub max: Vec3,
+            pub escape: u32,
+        }
+    }
+}
+
+fn gpu_types_shader() -> Shader {
+    let source = format!(
+        "#define_import_path {GPU_TYPES_IMPORT_PATH}\n\n{}\n{}",
+        GpuPrimative::WGSL,
+        GpuBvhNode::WGSL
+    );
+
+    Shader::from_wgsl(source, file!())
 }
 
 /// Material linked to shader that displays the scene with full lighting and
@@ -256,6 +277,10 @@ pub struct LitMaterial {
     pub clip_to_view: Mat4,
     #[storage(2, read_only)]
     pub primatives: Handle<ShaderStorageBuffer>,
+    #[storage(3, read_only)]
+    pub bvh: Handle<ShaderStorageBuffer>,
+    #[uniform(4)]
+    pub step_scale: f32,
 }
 
 #[derive(Resource)]
@@ -272,9 +297,17 @@ impl PrimativesBufferHandle {
     }
 }
 
-impl Material for SelectionMaterial {
-    fn fragment_shader() -> ShaderRef {
-        "shaders/selection_shader.wgsl".into()
+#[derive(Resource)]
+pub struct BvhBufferHandle(Handle<ShaderStorageBuffer>);
+
+impl BvhBufferHandle {
+    pub fn get_mut<'a>(
+        &self,
+        assets: &'a mut Assets<ShaderStorageBuffer>,
+    ) -> &'a mut ShaderStorageBuffer {
+        assets
+            .get_mut(&self.0)
+            .expect("ShaderStorageBuffer should exist")
     }
 }
 
@@ -283,3 +316,38 @@ impl Material for LitMaterial {
         "shaders/lit_shader.wgsl".into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_resource::{ShaderImport, ShaderType};
+
+    #[test]
+    fn gpu_types_declare_rust_layout() {
+        assert_eq!(
+            GpuPrimative::WGSL,
+            "struct GpuPrimative {
+    position: vec3<f32>,
+    is_subtract: u32,
+    scale: vec3<f32>,
+    blend: f32,
+    color: vec3<f32>,
+    rounding_radius: f32,
+    flags: u32,
+}
+"
+        );
+        assert_eq!(GpuPrimative::min_size().get(), 64);
+        assert_eq!(GpuBvhNode::min_size().get(), 32);
+    }
+
+    #[test]
+    fn gpu_types_shader_has_import_path() {
+        let shader = gpu_types_shader();
+
+        assert!(matches!(
+            &shader.import_path,
+            ShaderImport::Custom(path) if path == GPU_TYPES_IMPORT_PATH
+        ));
+    }
+}
@@ -0,0 +1,674 @@
+use std::path::Path;
+use std::{fs, io};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{camera, controls, geometry, global_id, node_id, parts};
+
+/// The format version this build writes. Older files are migrated as
+/// they're loaded.
+pub const VERSION: u32 = 2;
+
+pub struct SceneFilePlugin;
+
+impl Plugin for SceneFilePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SceneFileDialog::default());
+    }
+}
+
+/// A scene as stored on disk, in RON. Each part lists its members and the
+/// instances placed of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    #[serde(default)]
+    pub camera: Option<camera::CameraControls>,
+    #[serde(default)]
+    pub boxes: Vec<BoxRecord>,
+    #[serde(default)]
+    pub parts: Vec<PartRecord>,
+}
+
+impl Default for SceneFile {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            camera: None,
+            boxes: Vec::new(),
+            parts: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartRecord {
+    pub name: String,
+    /// Positioned relative to the part origin.
+    pub members: Vec<BoxRecord>,
+    #[serde(default)]
+    pub instances: Vec<InstanceRecord>,
+}
+
+/// A box as stored on disk, kept apart from [`geometry::BoxGeometry`] so
+/// the component can change without breaking files. Fields with a sensible
+/// default may be missing, as they will be from files written before them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoxRecord {
+    pub position: Vec3,
+    pub scale: Vec3,
+    pub color: [f32; 3],
+    #[serde(default)]
+    pub rounding: f32,
+    #[serde(default)]
+    pub blend: f32,
+    #[serde(default)]
+    pub is_subtract: bool,
+    #[serde(default)]
+    pub color_cut: bool,
+    #[serde(default = "shown")]
+    pub visible: bool,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub isolated: bool,
+    pub id: node_id::NodeId,
+}
+
+impl From<&geometry::BoxGeometry> for BoxRecord {
+    fn from(geometry: &geometry::BoxGeometry) -> Self {
+        Self {
+            position: geometry.position,
+            scale: geometry.scale,
+            color: geometry.color,
+            rounding: geometry.rounding,
+            blend: geometry.blend,
+            is_subtract: geometry.is_subtract,
+            color_cut: geometry.color_cut,
+            visible: geometry.visible,
+            locked: geometry.locked,
+            isolated: geometry.isolated,
+            id: geometry.id,
+        }
+    }
+}
+
+impl From<&BoxRecord> for geometry::BoxGeometry {
+    fn from(record: &BoxRecord) -> Self {
+        Self {
+            position: record.position,
+            scale: record.scale,
+            color: record.color,
+            rounding: record.rounding,
+            blend: record.blend,
+            is_subtract: record.is_subtract,
+            color_cut: record.color_cut,
+            visible: record.visible,
+            locked: record.locked,
+            isolated: record.isolated,
+            id: record.id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceRecord {
+    pub position: Vec3,
+    #[serde(default = "shown")]
+    pub visible: bool,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub isolated: bool,
+    pub id: node_id::NodeId,
+}
+
+/// Nodes are visible unless the file says otherwise.
+fn shown() -> bool {
+    true
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    /// Written by a newer build, in a format this one doesn't know.
+    UnsupportedVersion(u32),
+    /// An instance of a part the file doesn't have.
+    MissingPart {
+        instance: node_id::NodeId,
+        part: usize,
+    },
+    /// A node ID too large to be given a color.
+    InvalidId(node_id::NodeId),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "couldn't read the scene: {e}"),
+            LoadError::Parse(e) => write!(f, "couldn't parse the scene: {e}"),
+            LoadError::UnsupportedVersion(version) => write!(
+                f,
+                "the scene is in format version {version}, newer than this build reads"
+            ),
+            LoadError::MissingPart { instance, part } => {
+                write!(f, "instance {instance} refers to missing part {part}")
+            }
+            LoadError::InvalidId(id) => write!(f, "node ID {id} is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            LoadError::Parse(e) => Some(e),
+            LoadError::UnsupportedVersion(_)
+            | LoadError::MissingPart { .. }
+            | LoadError::InvalidId(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for LoadError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        LoadError::Parse(e)
+    }
+}
+
+/// Just enough of a file to tell which version it is. The first format
+/// didn't record one.
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default = "first_version")]
+    version: u32,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl SceneFile {
+    pub fn load(path: &Path) -> Result<Self, LoadError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse a file of any version, migrating older ones to the current
+    /// layout.
+    pub fn parse(text: &str) -> Result<Self, LoadError> {
+        let file = match ron::from_str::<Header>(text)?.version {
+            1 => ron::from_str::<v1::SceneFile>(text)?.migrate()?,
+            VERSION => ron::from_str(text)?,
+            version => return Err(LoadError::UnsupportedVersion(version)),
+        };
+
+        if let Some(id) = file.ids().find(|id| !id.is_valid()) {
+            return Err(LoadError::InvalidId(id));
+        }
+
+        Ok(file)
+    }
+
+    /// Record the scene in `world`: top-level boxes, parts with their
+    /// members and instances, and the camera.
+    pub fn capture(world: &mut World) -> Self {
+        let mut boxes: Vec<BoxRecord> = world
+            .query_filtered::<&geometry::BoxGeometry, Without<parts::MemberOf>>()
+            .iter(world)
+            .map(BoxRecord::from)
+            .collect();
+        boxes.sort_by_key(|b| b.id);
+
+        let mut definitions: Vec<(Entity, PartRecord)> = world
+            .query::<(Entity, &parts::PartDefinition, Option<&parts::PartMembers>)>()
+            .iter(world)
+            .map(|(entity, definition, members)| {
+                let mut members: Vec<BoxRecord> = members
+                    .into_iter()
+                    .flat_map(|members| members.iter())
+                    .filter_map(|member| world.get::<geometry::BoxGeometry>(member))
+                    .map(BoxRecord::from)
+                    .collect();
+                members.sort_by_key(|m| m.id);
+
+                let part = PartRecord {
+                    name: definition.name.clone(),
+                    members,
+                    instances: Vec::new(),
+                };
+
+                (entity, part)
+            })
+            .collect();
+        definitions.sort_by_key(|(entity, _)| *entity);
+
+        for instance in world.query::<&parts::PartInstance>().iter(world) {
+            let Some((_, part)) = definitions
+                .iter_mut()
+                .find(|(entity, _)| *entity == instance.definition)
+            else {
+                continue;
+            };
+
+            part.instances.push(InstanceRecord {
+                position: instance.position,
+                visible: instance.visible,
+                locked: instance.locked,
+                isolated: instance.isolated,
+                id: instance.id,
+            });
+        }
+
+        let mut parts: Vec<PartRecord> = definitions.into_iter().map(|(_, part)| part).collect();
+        for part in &mut parts {
+            part.instances.sort_by_key(|instance| instance.id);
+        }
+
+        Self {
+            version: VERSION,
+            camera: world.get_resource::<camera::CameraControls>().copied(),
+            boxes,
+            parts,
+        }
+    }
+
+    /// Spawn the scene's boxes, parts and instances into `world`.
+    pub fn spawn(&self, world: &mut World) {
+        for geometry in &self.boxes {
+            world.spawn(geometry::BoxGeometry::from(geometry));
+        }
+
+        for part in &self.parts {
+            let definition = world
+                .spawn(parts::PartDefinition {
+                    name: part.name.clone(),
+                })
+                .id();
+
+            for member in &part.members {
+                world.spawn((
+                    geometry::BoxGeometry::from(member),
+                    parts::MemberOf(definition),
+                ));
+            }
+
+            for instance in &part.instances {
+                world.spawn(parts::PartInstance {
+                    definition,
+                    position: instance.position,
+                    visible: instance.visible,
+                    locked: instance.locked,
+                    isolated: instance.isolated,
+                    id: instance.id,
+                });
+            }
+        }
+    }
+
+    /// Every node ID in the file.
+    fn ids(&self) -> impl Iterator<Item = node_id::NodeId> + '_ {
+        let members = self.parts.iter().flat_map(|part| &part.members);
+        let instances = self.parts.iter().flat_map(|part| &part.instances);
+
+        self.boxes
+            .iter()
+            .chain(members)
+            .map(|b| b.id)
+            .chain(instances.map(|instance| instance.id))
+    }
+
+    /// The largest node ID in the file.
+    fn max_id(&self) -> Option<node_id::NodeId> {
+        self.ids().max()
+    }
+
+    /// Write the scene in `world` to `path`.
+    pub fn save(world: &mut World, path: &Path) -> io::Result<()> {
+        let text =
+            ron::ser::to_string_pretty(&Self::capture(world), ron::ser::PrettyConfig::default())
+                .map_err(io::Error::other)?;
+
+        fs::write(path, text)
+    }
+
+    /// Replace the scene in `world` with the one at `path`. The current
+    /// scene is left alone if the file can't be loaded.
+    pub fn open(world: &mut World, path: &Path) -> Result<(), LoadError> {
+        let file = Self::load(path)?;
+
+        let old: Vec<Entity> = world
+            .query_filtered::<Entity, Or<(
+                With<geometry::BoxGeometry>,
+                With<parts::PartDefinition>,
+                With<parts::PartInstance>,
+            )>>()
+            .iter(world)
+            .collect();
+
+        // Members go with their definitions, so may already be gone
+        for entity in old {
+            if let Ok(entity) = world.get_entity_mut(entity) {
+                entity.despawn();
+            }
+        }
+
+        file.spawn(world);
+
+        if let Some(camera) = file.camera {
+            world.insert_resource(camera);
+        }
+
+        // New nodes mustn't take the IDs of loaded ones
+        if let Some(id) = file.max_id() {
+            world.resource_mut::<global_id::GlobalId>().skip_past(id);
+        }
+
+        // A part being placed may no longer exist
+        if let Some(mut mode) = world.get_resource_mut::<controls::ControlMode>()
+            && matches!(*mode, controls::ControlMode::PlacePart(_))
+        {
+            *mode = controls::ControlMode::Select;
+        }
+
+        Ok(())
+    }
+}
+
+/// The first format, from before files had versions. Instances were listed
+/// apart from their parts and referred to them by index.
+mod v1 {
+    use bevy::prelude::*;
+    use serde::Deserialize;
+
+    use super::{BoxRecord, LoadError};
+    use crate::node_id;
+
+    #[derive(Deserialize)]
+    pub struct SceneFile {
+        #[serde(default)]
+        boxes: Vec<BoxRecord>,
+        #[serde(default)]
+        parts: Vec<PartRecord>,
+        #[serde(default)]
+        instances: Vec<InstanceRecord>,
+    }
+
+    #[derive(Deserialize)]
+    struct PartRecord {
+        name: String,
+        members: Vec<BoxRecord>,
+    }
+
+    #[derive(Deserialize)]
+    struct InstanceRecord {
+        part: usize,
+        position: Vec3,
+        visible: bool,
+        locked: bool,
+        isolated: bool,
+        id: node_id::NodeId,
+    }
+
+    impl SceneFile {
+        /// Move each instance into the part it refers to.
+        pub fn migrate(self) -> Result<super::SceneFile, LoadError> {
+            let mut parts: Vec<super::PartRecord> = self
+                .parts
+                .into_iter()
+                .map(|part| super::PartRecord {
+                    name: part.name,
+                    members: part.members,
+                    instances: Vec::new(),
+                })
+                .collect();
+
+            for instance in self.instances {
+                let part = parts.get_mut(instance.part).ok_or(LoadError::MissingPart {
+                    instance: instance.id,
+                    part: instance.part,
+                })?;
+
+                part.instances.push(super::InstanceRecord {
+                    position: instance.position,
+                    visible: instance.visible,
+                    locked: instance.locked,
+                    isolated: instance.isolated,
+                    id: instance.id,
+                });
+            }
+
+            Ok(super::SceneFile {
+                boxes: self.boxes,
+                parts,
+                ..default()
+            })
+        }
+    }
+}
+
+/// Which way the scene file dialog opened from the File menu goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileAction {
+    Open,
+    Save,
+}
+
+/// State of the scene file dialog.
+#[derive(Resource, Debug)]
+pub struct SceneFileDialog {
+    /// What the dialog is doing, `None` while it's closed.
+    pub action: Option<FileAction>,
+    pub path: String,
+    /// Why the last attempt failed, shown in the dialog.
+    pub error: Option<String>,
+}
+
+impl Default for SceneFileDialog {
+    fn default() -> Self {
+        Self {
+            action: None,
+            path: "scene.scene".to_string(),
+            error: None,
+        }
+    }
+}
+
+impl SceneFileDialog {
+    /// Carry out the dialog's action on `world`, closing the dialog if it
+    /// worked.
+    pub fn run(world: &mut World, action: FileAction) {
+        let path = world.resource::<SceneFileDialog>().path.clone();
+        let path = Path::new(&path);
+
+        let result = match action {
+            FileAction::Open => SceneFile::open(world, path).map_err(|e| e.to_string()),
+            FileAction::Save => {
+                SceneFile::save(world, path).map_err(|e| format!("couldn't write the scene: {e}"))
+            }
+        };
+
+        let mut dialog = world.resource_mut::<SceneFileDialog>();
+        match result {
+            Ok(()) => {
+                dialog.action = None;
+                dialog.error = None;
+            }
+            Err(e) => dialog.error = Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+
+    fn bracket() -> PartRecord {
+        PartRecord {
+            name: "Bracket".to_string(),
+            members: vec![BoxRecord::from(&geometry::BoxGeometry::new(
+                Vec3::new(0.0, 1.0, 0.0),
+                1,
+            ))],
+            instances: vec![InstanceRecord {
+                position: Vec3::X * 5.0,
+                visible: true,
+                locked: false,
+                isolated: false,
+                id: node_id::NodeId::new(2),
+            }],
+        }
+    }
+
+    #[test]
+    fn instances_expand_their_part() {
+        let text = ron::to_string(&SceneFile {
+            boxes: vec![BoxRecord::from(&geometry::BoxGeometry::new(Vec3::ZERO, 0))],
+            parts: vec![bracket()],
+            ..default()
+        })
+        .unwrap();
+
+        let mut world = World::new();
+        SceneFile::parse(&text).unwrap().spawn(&mut world);
+
+        let mut state = SystemState::<geometry::ScenePrimitives>::new(&mut world);
+        let nodes = state.get(&world).collect_nodes();
+
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[1].name, "Bracket (2)");
+        assert_eq!(nodes[1].primitives[0].position, Vec3::new(5.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn first_version_files_are_migrated() {
+        let member =
+            ron::to_string(&BoxRecord::from(&geometry::BoxGeometry::new(Vec3::Y, 1))).unwrap();
+        let text = format!(
+            "(parts: [(name: \"Bracket\", members: [{member}])], \
+             instances: [(part: 0, position: (5.0, 0.0, 0.0), visible: true, \
+             locked: false, isolated: false, id: 2)])"
+        );
+
+        let file = SceneFile::parse(&text).unwrap();
+        assert_eq!(file.version, VERSION);
+        assert_eq!(file.parts[0].instances[0].id, node_id::NodeId::new(2));
+
+        let missing = text.replace("part: 0", "part: 3");
+        assert!(matches!(
+            SceneFile::parse(&missing),
+            Err(LoadError::MissingPart { part: 3, .. })
+        ));
+
+        assert!(matches!(
+            SceneFile::parse("(version: 99)"),
+            Err(LoadError::UnsupportedVersion(99))
+        ));
+    }
+
+    #[test]
+    fn ids_out_of_range_are_refused() {
+        let mut file = SceneFile {
+            parts: vec![bracket()],
+            ..default()
+        };
+        file.parts[0].instances[0].id = node_id::NodeId::new(1 << 24);
+        let text = ron::to_string(&file).unwrap();
+
+        assert!(matches!(
+            SceneFile::parse(&text),
+            Err(LoadError::InvalidId(id)) if id.value() == 1 << 24
+        ));
+
+        let migrated = "(boxes: [], parts: [(name: \"Bracket\", members: [])], \
+             instances: [(part: 0, position: (0.0, 0.0, 0.0), visible: true, \
+             locked: false, isolated: false, id: 4294967295)])";
+        assert!(matches!(
+            SceneFile::parse(migrated),
+            Err(LoadError::InvalidId(_))
+        ));
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        // A box from before cut colors and toggles, and an instance from
+        // before toggles
+        const FIXTURE: &str = r#"(
+            version: 2,
+            boxes: [(
+                position: (1.0, 2.0, 3.0),
+                scale: (2.5, 2.5, 2.5),
+                color: (1.0, 0.0, 0.0),
+                rounding: 0.5,
+                blend: 0.0,
+                is_subtract: true,
+                id: 4,
+            )],
+            parts: [(
+                name: "Bracket",
+                members: [],
+                instances: [(position: (0.0, 0.0, 0.0), id: 5)],
+            )],
+        )"#;
+
+        let file = SceneFile::parse(FIXTURE).unwrap();
+        let geometry = geometry::BoxGeometry::from(&file.boxes[0]);
+        assert_eq!(geometry.position, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(geometry.rounding, 0.5);
+        assert!(geometry.is_subtract);
+        assert!(!geometry.color_cut);
+        assert!(geometry.visible);
+        assert!(!geometry.locked);
+        assert!(!geometry.isolated);
+
+        let instance = &file.parts[0].instances[0];
+        assert!(instance.visible && !instance.locked && !instance.isolated);
+    }
+
+    #[test]
+    fn saved_scenes_open_as_they_were() {
+        let path = std::env::temp_dir().join("raystacean_saved_scenes_open.scene");
+
+        let mut world = World::new();
+        world.insert_resource(global_id::GlobalId::default());
+        world.insert_resource(camera::CameraControls {
+            distance: 3.0,
+            ..default()
+        });
+        SceneFile {
+            boxes: vec![BoxRecord::from(&geometry::BoxGeometry::new(Vec3::ZERO, 0))],
+            parts: vec![bracket()],
+            ..default()
+        }
+        .spawn(&mut world);
+
+        SceneFile::save(&mut world, &path).unwrap();
+        let saved = SceneFile::capture(&mut world);
+
+        let mut loaded = World::new();
+        loaded.insert_resource(global_id::GlobalId::default());
+        loaded.spawn(geometry::BoxGeometry::new(Vec3::X, 7));
+        SceneFile::open(&mut loaded, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let reloaded = SceneFile::capture(&mut loaded);
+        assert_eq!(
+            ron::to_string(&reloaded).unwrap(),
+            ron::to_string(&saved).unwrap()
+        );
+        assert_eq!(reloaded.camera.unwrap().distance, 3.0);
+
+        // IDs carry on after the largest loaded
+        assert_eq!(loaded.resource_mut::<global_id::GlobalId>().next(), 3);
+    }
+}
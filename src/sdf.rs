@@ -0,0 +1,491 @@
+use bevy::math::bounding::Aabb3d;
+use bevy::prelude::*;
+
+use crate::geometry;
+
+/// CPU mirror of the `SdfResult` in `sdf.wgsl`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SdfResult {
+    pub dist: f32,
+    pub color: Vec3,
+}
+
+impl SdfResult {
+    pub fn new(dist: f32, color: Vec3) -> Self {
+        Self { dist, color }
+    }
+}
+
+/// A range of distances, bounding the field over a region of space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Interval {
+    pub fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+}
+
+/// Distance to nothing, the value `map` starts from in the shaders.
+const EMPTY: SdfResult = SdfResult {
+    dist: 100.0,
+    color: Vec3::ZERO,
+};
+
+/// The scene's distance field evaluated on the CPU. Mirrors `map` in
+/// `lit_shader.wgsl`, evaluating every primitive where the shader skips
+/// distant ones with its BVH.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    primitives: Vec<geometry::BoxGeometry>,
+}
+
+impl Scene {
+    /// Build from primitives in evaluation order, as returned by
+    /// `ScenePrimitives::collect`.
+    pub fn new(primitives: Vec<geometry::BoxGeometry>) -> Self {
+        Self { primitives }
+    }
+
+    pub fn primitives(&self) -> &[geometry::BoxGeometry] {
+        &self.primitives
+    }
+
+    /// The full field seen by the renderer, including the ground plane.
+    pub fn map(&self, p: Vec3) -> SdfResult {
+        min_sdf(self.map_geometry(p), sd_ground(p))
+    }
+
+    /// Surface normal from the gradient of the full field, matching
+    /// `calc_normal` in the lit shader.
+    pub fn normal(&self, p: Vec3) -> Vec3 {
+        let e = 0.001;
+        let dx = self.map(p + Vec3::X * e).dist - self.map(p - Vec3::X * e).dist;
+        let dy = self.map(p + Vec3::Y * e).dist - self.map(p - Vec3::Y * e).dist;
+        let dz = self.map(p + Vec3::Z * e).dist - self.map(p - Vec3::Z * e).dist;
+        Vec3::new(dx, dy, dz).normalize()
+    }
+
+    /// The field of the scene's geometry alone, without the ground plane.
+    pub fn map_geometry(&self, p: Vec3) -> SdfResult {
+        self.primitives
+            .iter()
+            .fold(EMPTY, |sdf, primitive| apply_primitive(sdf, p, primitive))
+    }
+
+    /// Surface normal from the gradient of the geometry alone.
+    pub fn geometry_normal(&self, p: Vec3) -> Vec3 {
+        let e = 0.001;
+        let d =
+            |offset: Vec3| self.map_geometry(p + offset).dist - self.map_geometry(p - offset).dist;
+        Vec3::new(d(Vec3::X * e), d(Vec3::Y * e), d(Vec3::Z * e)).normalize_or_zero()
+    }
+
+    /// The primitive, out of those matching `filter`, whose own surface is
+    /// nearest to `p`. On the scene's surface this is the primitive that
+    /// made it: cut faces belong to the cutter and blends to whichever side
+    /// is closer.
+    pub fn nearest_primitive(
+        &self,
+        p: Vec3,
+        filter: impl Fn(&geometry::BoxGeometry) -> bool,
+    ) -> Option<&geometry::BoxGeometry> {
+        self.primitives
+            .iter()
+            .filter(|b| filter(b))
+            .map(|b| {
+                let d = sd_box(p - b.position, b.scale, b.rounding_radius(), Vec3::ZERO);
+                (d.dist.abs(), b)
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, b)| b)
+    }
+
+    /// Bounds on `map_geometry` over every point in `region`.
+    pub fn map_interval(&self, region: Aabb3d) -> Interval {
+        let empty = Interval::new(EMPTY.dist, EMPTY.dist);
+
+        self.primitives.iter().fold(empty, |sdf, primitive| {
+            apply_primitive_interval(sdf, region, primitive)
+        })
+    }
+}
+
+fn apply_primitive(sdf: SdfResult, p: Vec3, primitive: &geometry::BoxGeometry) -> SdfResult {
+    let b = sd_box(
+        p - primitive.position,
+        primitive.scale,
+        primitive.rounding_radius(),
+        primitive.color.into(),
+    );
+
+    if primitive.is_subtract {
+        if primitive.color_cut {
+            return sd_smooth_subtract(b, sdf, primitive.blend);
+        }
+
+        return SdfResult::new(
+            op_smooth_subtract(b.dist, sdf.dist, primitive.blend),
+            sdf.color,
+        );
+    }
+
+    sd_smooth_union(sdf, b, primitive.blend)
+}
+
+// The smooth operations never decrease as either distance grows (or as the
+// cutter's distance shrinks), so evaluating them at the ends of the input
+// intervals bounds the result.
+fn apply_primitive_interval(
+    sdf: Interval,
+    region: Aabb3d,
+    primitive: &geometry::BoxGeometry,
+) -> Interval {
+    let b = sd_box_interval(
+        Vec3::from(region.min) - primitive.position,
+        Vec3::from(region.max) - primitive.position,
+        primitive.scale,
+        primitive.rounding_radius(),
+    );
+    let k = primitive.blend;
+
+    if primitive.is_subtract {
+        Interval::new(
+            op_smooth_subtract(b.max, sdf.min, k),
+            op_smooth_subtract(b.min, sdf.max, k),
+        )
+    } else {
+        Interval::new(
+            op_smooth_union(sdf.min, b.min, k),
+            op_smooth_union(sdf.max, b.max, k),
+        )
+    }
+}
+
+pub fn sd_box(p: Vec3, b: Vec3, r: f32, color: Vec3) -> SdfResult {
+    let q = p.abs() - b + r;
+    let d = q.max(Vec3::ZERO).length() + q.max_element().min(0.0) - r;
+    SdfResult::new(d, color)
+}
+
+/// `sd_box` over every point between `min` and `max`.
+pub fn sd_box_interval(min: Vec3, max: Vec3, b: Vec3, r: f32) -> Interval {
+    // |p| is smallest at the point of the range nearest zero
+    let straddles = min.cmple(Vec3::ZERO) & max.cmpge(Vec3::ZERO);
+    let abs_min = Vec3::select(straddles, Vec3::ZERO, min.abs().min(max.abs()));
+    let abs_max = min.abs().max(max.abs());
+
+    let q_min = abs_min - b + r;
+    let q_max = abs_max - b + r;
+
+    Interval::new(
+        q_min.max(Vec3::ZERO).length() + q_min.max_element().min(0.0) - r,
+        q_max.max(Vec3::ZERO).length() + q_max.max_element().min(0.0) - r,
+    )
+}
+
+pub fn min_sdf(s1: SdfResult, s2: SdfResult) -> SdfResult {
+    if s1.dist < s2.dist { s1 } else { s2 }
+}
+
+pub fn sd_ground(p: Vec3) -> SdfResult {
+    SdfResult::new(p.y, grid_color(p))
+}
+
+pub fn grid_color(pos: Vec3) -> Vec3 {
+    let minor_scale = 2.5;
+    let major_scale = 0.5;
+    let line_thickness = 0.01;
+
+    let on_line = |p: Vec2| {
+        let g = (p - p.floor() - Vec2::splat(0.5)).abs();
+        g.x < line_thickness || g.y < line_thickness
+    };
+
+    if on_line(pos.xz() * major_scale) {
+        Vec3::new(0.1, 0.3, 0.6)
+    } else if on_line(pos.xz() * minor_scale) {
+        Vec3::new(0.4, 0.6, 0.9)
+    } else {
+        Vec3::new(0.95, 0.97, 1.0)
+    }
+}
+
+// Cubic polynomial adapted from: https://iquilezles.org/articles/smin/
+pub fn op_smooth_subtract(s1: f32, s2: f32, k: f32) -> f32 {
+    let n = (s1 + s2).abs() / (6.0 * k);
+    let h = 1.0 - n.min(1.0);
+    let w = h * h * h;
+    let s = w * k;
+
+    -s1.min(-s2) + s
+}
+
+/// Subtraction with color blending, the cut surface takes the cutter's color
+/// and blends into the base color across the smooth region.
+pub fn sd_smooth_subtract(cutter: SdfResult, base: SdfResult, k: f32) -> SdfResult {
+    let n = (cutter.dist + base.dist).abs() / (6.0 * k);
+    let h = 1.0 - n.min(1.0);
+    let w = h * h * h;
+    let s = w * k;
+    let m = w * 0.5;
+
+    if -cutter.dist > base.dist {
+        SdfResult::new(-cutter.dist + s, cutter.color.lerp(base.color, m))
+    } else {
+        SdfResult::new(base.dist + s, base.color.lerp(cutter.color, m))
+    }
+}
+
+// Cubic polynomial adapted from: https://iquilezles.org/articles/smin/
+pub fn op_smooth_union(s1: f32, s2: f32, k: f32) -> f32 {
+    let n = (s1 - s2).abs() / (6.0 * k);
+    let h = 1.0 - n.min(1.0);
+    let w = h * h * h;
+    let s = w * k;
+
+    s1.min(s2) - s
+}
+
+// cubic polynomial with color blending. Taken from:
+// https://iquilezles.org/articles/smin/
+pub fn sd_smooth_union(s1: SdfResult, s2: SdfResult, k: f32) -> SdfResult {
+    let n = (s1.dist - s2.dist).abs() / (6.0 * k);
+    let h = 1.0 - n.min(1.0);
+    let w = h * h * h;
+    let s = w * k;
+    let m = w * 0.5;
+
+    if s1.dist < s2.dist {
+        SdfResult::new(s1.dist - s, s1.color.lerp(s2.color, m))
+    } else {
+        SdfResult::new(s2.dist - s, s1.color.lerp(s2.color, 1.0 - m))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_id::NodeId;
+
+    const RED: [f32; 3] = [1.0, 0.0, 0.0];
+    const BLUE: [f32; 3] = [0.0, 0.0, 1.0];
+
+    fn unit_box(id: u32, position: Vec3, color: [f32; 3]) -> geometry::BoxGeometry {
+        geometry::BoxGeometry {
+            position,
+            scale: Vec3::ONE,
+            color,
+            rounding: 0.0,
+            blend: 0.0,
+            is_subtract: false,
+            color_cut: false,
+            visible: true,
+            locked: false,
+            isolated: false,
+            id: NodeId::new(id),
+        }
+    }
+
+    fn assert_within(value: f32, interval: Interval) {
+        assert!(
+            interval.min <= value && value <= interval.max,
+            "{value} outside {interval:?}"
+        );
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn box_distances() {
+        let b = Vec3::ONE;
+
+        assert_close(sd_box(Vec3::ZERO, b, 0.0, Vec3::ZERO).dist, -1.0);
+        assert_close(
+            sd_box(Vec3::new(2.0, 0.0, 0.0), b, 0.0, Vec3::ZERO).dist,
+            1.0,
+        );
+        assert_close(
+            sd_box(Vec3::new(2.0, 2.0, 0.0), b, 0.0, Vec3::ZERO).dist,
+            2.0_f32.sqrt(),
+        );
+    }
+
+    #[test]
+    fn rounded_box_pulls_in_corners() {
+        let d = sd_box(Vec3::new(2.0, 2.0, 0.0), Vec3::ONE, 0.5, Vec3::ZERO).dist;
+
+        assert_close(d, 1.5 * 2.0_f32.sqrt() - 0.5);
+        // Faces are unaffected
+        assert_close(
+            sd_box(Vec3::new(2.0, 0.0, 0.0), Vec3::ONE, 0.5, Vec3::ZERO).dist,
+            1.0,
+        );
+    }
+
+    #[test]
+    fn smooth_union_without_blend_is_min() {
+        let a = SdfResult::new(1.0, Vec3::X);
+        let b = SdfResult::new(2.0, Vec3::Z);
+
+        assert_eq!(sd_smooth_union(a, b, 0.0), a);
+        assert_eq!(sd_smooth_union(b, a, 0.0), a);
+    }
+
+    #[test]
+    fn smooth_union_blends_equal_distances() {
+        let a = SdfResult::new(1.0, Vec3::X);
+        let b = SdfResult::new(1.0, Vec3::Z);
+
+        let result = sd_smooth_union(a, b, 0.25);
+
+        assert_close(result.dist, 0.75);
+        assert_eq!(result.color, Vec3::new(0.5, 0.0, 0.5));
+    }
+
+    #[test]
+    fn smooth_subtract_without_blend_is_max() {
+        assert_close(op_smooth_subtract(-0.5, -1.0, 0.0), 0.5);
+        assert_close(op_smooth_subtract(2.0, -1.0, 0.0), -1.0);
+        // Equal and opposite distances get the full blend added
+        assert_close(op_smooth_subtract(0.05, -0.05, 0.1), 0.05);
+    }
+
+    #[test]
+    fn empty_scene_is_far_away() {
+        let scene = Scene::default();
+
+        assert_close(scene.map_geometry(Vec3::ZERO).dist, 100.0);
+        assert_close(scene.map(Vec3::new(0.0, 3.0, 0.0)).dist, 3.0);
+    }
+
+    #[test]
+    fn ground_uses_grid_color() {
+        let scene = Scene::default();
+        let result = scene.map(Vec3::new(0.3, 0.5, 0.3));
+
+        assert_close(result.dist, 0.5);
+        assert_eq!(result.color, Vec3::new(0.95, 0.97, 1.0));
+        assert_eq!(
+            grid_color(Vec3::new(1.0, 0.0, 0.3)),
+            Vec3::new(0.1, 0.3, 0.6)
+        );
+        assert_eq!(
+            grid_color(Vec3::new(0.2, 0.0, 0.3)),
+            Vec3::new(0.4, 0.6, 0.9)
+        );
+    }
+
+    #[test]
+    fn subtraction_cuts_earlier_primitives_only() {
+        let base = unit_box(0, Vec3::ZERO, RED);
+        let cutter = geometry::BoxGeometry {
+            scale: Vec3::splat(0.5),
+            is_subtract: true,
+            ..unit_box(1, Vec3::new(0.0, 1.0, 0.0), BLUE)
+        };
+        let later = unit_box(2, Vec3::new(3.0, 0.0, 0.0), RED);
+
+        let scene = Scene::new(vec![base, cutter, later]);
+
+        // Inside the pocket left by the cutter
+        let pocket = scene.map_geometry(Vec3::new(0.0, 0.75, 0.0));
+        assert_close(pocket.dist, 0.25);
+        assert_eq!(pocket.color, Vec3::from(RED));
+
+        // Solid material below the pocket
+        assert_close(scene.map_geometry(Vec3::new(0.0, -0.5, 0.0)).dist, -0.5);
+
+        // The box placed after the cutter is untouched
+        assert_close(scene.map_geometry(Vec3::new(3.0, 0.0, 0.0)).dist, -1.0);
+    }
+
+    #[test]
+    fn color_cut_paints_the_cut_surface() {
+        let base = unit_box(0, Vec3::ZERO, RED);
+        let cutter = geometry::BoxGeometry {
+            scale: Vec3::splat(0.5),
+            is_subtract: true,
+            color_cut: true,
+            ..unit_box(1, Vec3::new(0.0, 1.0, 0.0), BLUE)
+        };
+
+        let scene = Scene::new(vec![base, cutter]);
+
+        // Floor of the pocket is the cutter's surface
+        let floor = scene.map_geometry(Vec3::new(0.0, 0.51, 0.0));
+        assert_close(floor.dist, 0.01);
+        assert_eq!(floor.color, Vec3::from(BLUE));
+
+        // Outer faces keep the base color
+        let side = scene.map_geometry(Vec3::new(1.01, -0.5, 0.0));
+        assert_eq!(side.color, Vec3::from(RED));
+    }
+
+    #[test]
+    fn box_interval_contains_samples() {
+        let (min, max) = (Vec3::new(-0.5, 0.5, 1.5), Vec3::new(0.5, 1.5, 2.5));
+        let interval = sd_box_interval(min, max, Vec3::ONE, 0.25);
+
+        for x in 0..=4 {
+            for y in 0..=4 {
+                for z in 0..=4 {
+                    let t = Vec3::new(x as f32, y as f32, z as f32) / 4.0;
+                    let p = min + (max - min) * t;
+                    let d = sd_box(p, Vec3::ONE, 0.25, Vec3::ZERO).dist;
+
+                    assert_within(d, interval);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scene_interval_contains_samples() {
+        let base = geometry::BoxGeometry {
+            blend: 0.2,
+            ..unit_box(0, Vec3::ZERO, RED)
+        };
+        let cutter = geometry::BoxGeometry {
+            scale: Vec3::splat(0.5),
+            blend: 0.1,
+            is_subtract: true,
+            ..unit_box(1, Vec3::new(0.5, 1.0, 0.0), BLUE)
+        };
+        let scene = Scene::new(vec![base, cutter]);
+
+        let region = Aabb3d::new(Vec3::new(0.5, 0.75, 0.25), Vec3::splat(0.5));
+        let interval = scene.map_interval(region);
+
+        for i in 0..=8 {
+            let t = Vec3::new(i as f32, (8 - i) as f32, (i * 3 % 8) as f32) / 8.0;
+            let p = Vec3::from(region.min) + Vec3::from(region.max - region.min) * t;
+            let d = scene.map_geometry(p).dist;
+
+            assert_within(d, interval);
+        }
+    }
+
+    #[test]
+    fn scene_applies_rounding_radius() {
+        let rounded = geometry::BoxGeometry {
+            rounding: 0.5,
+            ..unit_box(0, Vec3::ZERO, RED)
+        };
+
+        let scene = Scene::new(vec![rounded]);
+
+        assert_close(
+            scene.map_geometry(Vec3::new(2.0, 2.0, 0.0)).dist,
+            1.5 * 2.0_f32.sqrt() - 0.5,
+        );
+    }
+}
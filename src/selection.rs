@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{controls, events, geometry, node_id};
+use crate::{controls, events, geometry, parts, picking};
 
 pub struct SelectionPlugin;
 
@@ -10,7 +10,8 @@ pub struct Selected;
 impl Plugin for SelectionPlugin {
     fn build(&self, app: &mut bevy::app::App) {
         app.add_observer(box_selection)
-            .add_observer(on_geometry_added);
+            .add_observer(on_geometry_added)
+            .add_observer(on_node_selected);
     }
 }
 
@@ -18,8 +19,9 @@ fn box_selection(
     _trigger: Trigger<events::PlaneClicked>,
     control_mode: Res<controls::ControlMode>,
     selected: Query<Entity, With<Selected>>,
-    boxes: Query<(Entity, &geometry::BoxGeometry)>,
-    ev: EventReader<events::PixelColorUnderCursor>,
+    boxes: Query<(Entity, &geometry::BoxGeometry), Without<parts::MemberOf>>,
+    instances: Query<(Entity, &parts::PartInstance)>,
+    picker: picking::ScenePicker,
     mut commands: Commands,
 ) {
     match control_mode.selection_policy() {
@@ -27,22 +29,34 @@ fn box_selection(
         controls::SelectionPolicy::Single => {
             deselect_selected(selected, &mut commands);
 
-            select_under_cursor(ev, commands, boxes);
+            select_under_cursor(picker, commands, boxes, instances);
         }
     }
 }
 
 fn select_under_cursor(
-    mut ev: EventReader<events::PixelColorUnderCursor>,
+    picker: picking::ScenePicker,
     mut commands: Commands,
-    boxes: Query<(Entity, &geometry::BoxGeometry)>,
+    boxes: Query<(Entity, &geometry::BoxGeometry), Without<parts::MemberOf>>,
+    instances: Query<(Entity, &parts::PartInstance)>,
 ) {
-    if let Some(latest) = ev.read().last() {
-        let id = node_id::NodeId::from_color(latest.color());
+    let Some(id) = picker.pick_under_cursor().and_then(|hit| hit.node) else {
+        return;
+    };
 
-        if let Some(newly_selected) = boxes.iter().find(|(_, geometry)| geometry.id == id) {
-            commands.entity(newly_selected.0).insert(Selected);
-        }
+    // Locked nodes still block the ray, but can't be selected
+    let newly_selected = boxes
+        .iter()
+        .map(|(entity, geometry)| (entity, geometry.id, geometry.locked))
+        .chain(
+            instances
+                .iter()
+                .map(|(entity, instance)| (entity, instance.id, instance.locked)),
+        )
+        .find(|(_, node_id, locked)| *node_id == id && !locked);
+
+    if let Some((entity, _, _)) = newly_selected {
+        commands.entity(entity).insert(Selected);
     }
 }
 
@@ -55,6 +69,15 @@ fn on_geometry_added(
     commands.entity(event.entity).insert(Selected);
 }
 
+fn on_node_selected(
+    event: Trigger<events::NodeSelected>,
+    selected: Query<Entity, With<Selected>>,
+    mut commands: Commands,
+) {
+    deselect_selected(selected, &mut commands);
+    commands.entity(event.entity).insert(Selected);
+}
+
 fn deselect_selected(selected: Query<Entity, With<Selected>>, commands: &mut Commands) {
     for entity in selected.iter() {
         commands.entity(entity).remove::<Selected>();
@@ -7,7 +7,10 @@ use bevy_egui::{
     egui::{self, RichText},
 };
 
-use crate::{controls, geometry, selection};
+use crate::{
+    bounds, controls, events, export, field_quality, geometry, global_id, node_id, parts,
+    rendering, scene_file, sdf, selection,
+};
 
 pub struct UiPlugin;
 
@@ -16,15 +19,54 @@ impl Plugin for UiPlugin {
         app.add_plugins(EguiPlugin::default()).add_systems(
             EguiPrimaryContextPass,
             (
+                menu_bar_ui,
                 toolbar_ui,
                 inspector_ui,
+                instance_inspector_ui,
+                parts_ui,
+                scene_list_ui,
                 place_geometry_tooltop,
                 diagnostics_ui,
+                export_dialog_ui,
+                scene_file_dialog_ui,
             ),
         );
     }
 }
 
+/// The menu bar along the top of the window. Windows anchored to the top
+/// leave room for it.
+fn menu_bar_ui(
+    mut contexts: EguiContexts,
+    mut export_dialog: ResMut<export::ExportDialog>,
+    mut file_dialog: ResMut<scene_file::SceneFileDialog>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+
+    egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+        egui::MenuBar::new().ui(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                if ui.button("Open scene…").clicked() {
+                    file_dialog.action = Some(scene_file::FileAction::Open);
+                    file_dialog.error = None;
+                }
+                if ui.button("Save scene…").clicked() {
+                    file_dialog.action = Some(scene_file::FileAction::Save);
+                    file_dialog.error = None;
+                }
+
+                ui.separator();
+
+                if ui.button("Export…").clicked() {
+                    export_dialog.open = true;
+                }
+            });
+        });
+    });
+
+    Ok(())
+}
+
 pub fn toolbar_ui(
     mut contexts: EguiContexts,
     mut control_mode: ResMut<controls::ControlMode>,
@@ -56,8 +98,10 @@ pub fn toolbar_ui(
 
 fn inspector_ui(
     mut contexts: EguiContexts,
-    mut selected: Query<&mut geometry::BoxGeometry, With<selection::Selected>>,
+    mut selected: Query<(Entity, &mut geometry::BoxGeometry), With<selection::Selected>>,
     control_mode: ResMut<controls::ControlMode>,
+    mut global_id: ResMut<global_id::GlobalId>,
+    mut commands: Commands,
 ) -> Result {
     // We only want to show this ui in select mode
     if *control_mode != controls::ControlMode::Select {
@@ -65,57 +109,312 @@ fn inspector_ui(
     }
 
     let context = contexts.ctx_mut()?;
-    if let Ok(mut selected) = selected.single_mut() {
+    if let Ok((entity, mut selected)) = selected.single_mut() {
         egui::Window::new("Box").show(context, |ui| {
-            egui::Frame::group(ui.style())
-                .fill(egui::Color32::from_gray(30))
-                .corner_radius(5.0)
-                .inner_margin(egui::Margin::same(8))
-                .show(ui, |ui| {
-                    egui::Grid::new("properties").striped(true).show(ui, |ui| {
-                        ui.label("Position");
-                        ui.horizontal(|ui| {
-                            ui.add(egui::DragValue::new(&mut selected.position.x).speed(0.1));
-                            ui.add(egui::DragValue::new(&mut selected.position.y).speed(0.1));
-                            ui.add(egui::DragValue::new(&mut selected.position.z).speed(0.1));
-                        });
-                        ui.end_row();
+            box_properties(ui, "properties", &mut selected);
 
-                        ui.label("Scale");
-                        ui.horizontal(|ui| {
-                            ui.add(egui::DragValue::new(&mut selected.scale.x).speed(0.1));
-                            ui.add(egui::DragValue::new(&mut selected.scale.y).speed(0.1));
-                            ui.add(egui::DragValue::new(&mut selected.scale.z).speed(0.1));
-                        });
-                        ui.end_row();
+            if ui
+                .button("Make part")
+                .on_hover_text("turn this box into a reusable part")
+                .clicked()
+            {
+                let instance =
+                    parts::make_part_from_box(entity, &mut selected, &mut global_id, &mut commands);
+                commands.trigger(events::GeometryAdded { entity: instance });
+            }
+        });
+    }
 
-                        ui.label("Picker");
-                        ui.color_edit_button_rgb(&mut selected.color);
-                        ui.end_row();
+    Ok(())
+}
 
-                        ui.label("Rounding");
-                        ui.add(egui::Slider::new(&mut selected.rounding, 0.0..=1.0));
-                        ui.end_row();
+fn instance_inspector_ui(
+    mut contexts: EguiContexts,
+    mut selected: Query<&mut parts::PartInstance, With<selection::Selected>>,
+    definitions: Query<&parts::PartDefinition>,
+    control_mode: Res<controls::ControlMode>,
+) -> Result {
+    if *control_mode != controls::ControlMode::Select {
+        return Ok(());
+    }
+
+    let context = contexts.ctx_mut()?;
+    if let Ok(mut selected) = selected.single_mut() {
+        let name = definitions
+            .get(selected.definition)
+            .map(|definition| definition.name.as_str())
+            .unwrap_or("Missing part");
 
-                        ui.label("Blend");
-                        ui.add(egui::Slider::new(&mut selected.blend, 0.0..=1.0));
-                        ui.end_row();
+        egui::Window::new("Part Instance").show(context, |ui| {
+            property_frame(ui, |ui| {
+                egui::Grid::new("instance").striped(true).show(ui, |ui| {
+                    ui.label("Part");
+                    ui.label(name);
+                    ui.end_row();
 
-                        ui.add(egui::Checkbox::new(&mut selected.is_subtract, "Subtract"));
-                        ui.end_row();
+                    ui.label("Position");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut selected.position.x).speed(0.1));
+                        ui.add(egui::DragValue::new(&mut selected.position.y).speed(0.1));
+                        ui.add(egui::DragValue::new(&mut selected.position.z).speed(0.1));
                     });
+                    ui.end_row();
                 });
+            });
         });
     }
 
     Ok(())
 }
 
+/// Lists part definitions, allowing their members to be edited and new
+/// instances to be placed.
+fn parts_ui(
+    mut contexts: EguiContexts,
+    mut definitions: Query<(
+        Entity,
+        &mut parts::PartDefinition,
+        Option<&parts::PartMembers>,
+    )>,
+    mut members: Query<&mut geometry::BoxGeometry, With<parts::MemberOf>>,
+    mut control_mode: ResMut<controls::ControlMode>,
+    mut global_id: ResMut<global_id::GlobalId>,
+    mut commands: Commands,
+) -> Result {
+    if definitions.is_empty() {
+        return Ok(());
+    }
+
+    let context = contexts.ctx_mut()?;
+
+    egui::Window::new("Parts")
+        .anchor(egui::Align2::LEFT_TOP, egui::vec2(10.0, 40.0))
+        .show(context, |ui| {
+            for (entity, mut definition, part_members) in &mut definitions {
+                ui.push_id(entity, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut definition.name);
+
+                        if ui
+                            .button("Place")
+                            .on_hover_text("place an instance")
+                            .clicked()
+                        {
+                            *control_mode = controls::ControlMode::PlacePart(entity);
+                        }
+                    });
+
+                    ui.collapsing("Members", |ui| {
+                        let mut iter =
+                            members.iter_many_mut(part_members.into_iter().flat_map(|m| m.iter()));
+                        while let Some(mut member) = iter.fetch_next() {
+                            ui.push_id(member.id.to_string(), |ui| {
+                                box_properties(ui, "member", &mut member);
+                            });
+                        }
+
+                        if ui.button("Add box").clicked() {
+                            let member = geometry::BoxGeometry::new(Vec3::ZERO, global_id.next());
+                            // sit the box on the part origin
+                            let y = member.scale.y;
+
+                            commands.spawn((member.with_y(y), parts::MemberOf(entity)));
+                        }
+                    });
+                });
+
+                ui.separator();
+            }
+        });
+
+    Ok(())
+}
+
+/// Lists the top-level scene nodes with their visibility, lock and isolate
+/// toggles. Clicking a node's name selects it.
+fn scene_list_ui(
+    mut contexts: EguiContexts,
+    mut boxes: Query<(Entity, &mut geometry::BoxGeometry), Without<parts::MemberOf>>,
+    mut instances: Query<(Entity, &mut parts::PartInstance)>,
+    definitions: Query<&parts::PartDefinition>,
+    selected: Query<Entity, With<selection::Selected>>,
+    mut commands: Commands,
+) -> Result {
+    let mut nodes: Vec<(node_id::NodeId, Entity)> = boxes
+        .iter()
+        .map(|(entity, geometry)| (geometry.id, entity))
+        .chain(
+            instances
+                .iter()
+                .map(|(entity, instance)| (instance.id, entity)),
+        )
+        .collect();
+
+    if nodes.is_empty() {
+        return Ok(());
+    }
+
+    nodes.sort();
+
+    let context = contexts.ctx_mut()?;
+    let mut newly_isolated = None;
+
+    egui::Window::new("Scene")
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10.0, -10.0))
+        .show(context, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for (id, entity) in nodes {
+                        ui.horizontal(|ui| {
+                            let name = if let Ok((_, mut geometry)) = boxes.get_mut(entity) {
+                                if let Some((visible, locked, isolated)) = node_toggles(
+                                    ui,
+                                    geometry.visible,
+                                    geometry.locked,
+                                    geometry.isolated,
+                                ) {
+                                    if isolated && !geometry.isolated {
+                                        newly_isolated = Some(entity);
+                                    }
+                                    geometry.visible = visible;
+                                    geometry.locked = locked;
+                                    geometry.isolated = isolated;
+                                }
+
+                                format!("Box {id}")
+                            } else if let Ok((_, mut instance)) = instances.get_mut(entity) {
+                                if let Some((visible, locked, isolated)) = node_toggles(
+                                    ui,
+                                    instance.visible,
+                                    instance.locked,
+                                    instance.isolated,
+                                ) {
+                                    if isolated && !instance.isolated {
+                                        newly_isolated = Some(entity);
+                                    }
+                                    instance.visible = visible;
+                                    instance.locked = locked;
+                                    instance.isolated = isolated;
+                                }
+
+                                let part = definitions
+                                    .get(instance.definition)
+                                    .map(|definition| definition.name.as_str())
+                                    .unwrap_or("Missing part");
+
+                                format!("{part} ({id})")
+                            } else {
+                                return;
+                            };
+
+                            let is_selected = selected.contains(entity);
+                            if ui.selectable_label(is_selected, name).clicked() {
+                                commands.trigger(events::NodeSelected { entity });
+                            }
+                        });
+                    }
+                });
+        });
+
+    // Only one node is isolated at a time, isolating another replaces it
+    if let Some(isolated) = newly_isolated {
+        for (entity, mut geometry) in &mut boxes {
+            if entity != isolated && geometry.isolated {
+                geometry.isolated = false;
+            }
+        }
+
+        for (entity, mut instance) in &mut instances {
+            if entity != isolated && instance.isolated {
+                instance.isolated = false;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Visibility, lock and isolate toggles for a scene node. Returns the new
+/// state if any toggle was clicked.
+fn node_toggles(
+    ui: &mut egui::Ui,
+    mut visible: bool,
+    mut locked: bool,
+    mut isolated: bool,
+) -> Option<(bool, bool, bool)> {
+    let changed = ui
+        .toggle_value(&mut visible, "👁")
+        .on_hover_text("visible")
+        .changed()
+        | ui.toggle_value(&mut locked, "🔒")
+            .on_hover_text("locked")
+            .changed()
+        | ui.toggle_value(&mut isolated, "🎯")
+            .on_hover_text("isolate")
+            .changed();
+
+    changed.then_some((visible, locked, isolated))
+}
+
+fn property_frame(ui: &mut egui::Ui, add_contents: impl FnOnce(&mut egui::Ui)) {
+    egui::Frame::group(ui.style())
+        .fill(egui::Color32::from_gray(30))
+        .corner_radius(5.0)
+        .inner_margin(egui::Margin::same(8))
+        .show(ui, add_contents);
+}
+
+fn box_properties(ui: &mut egui::Ui, id: &str, geometry: &mut geometry::BoxGeometry) {
+    property_frame(ui, |ui| {
+        egui::Grid::new(id).striped(true).show(ui, |ui| {
+            ui.label("Position");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut geometry.position.x).speed(0.1));
+                ui.add(egui::DragValue::new(&mut geometry.position.y).speed(0.1));
+                ui.add(egui::DragValue::new(&mut geometry.position.z).speed(0.1));
+            });
+            ui.end_row();
+
+            ui.label("Scale");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut geometry.scale.x).speed(0.1));
+                ui.add(egui::DragValue::new(&mut geometry.scale.y).speed(0.1));
+                ui.add(egui::DragValue::new(&mut geometry.scale.z).speed(0.1));
+            });
+            ui.end_row();
+
+            ui.label("Picker");
+            ui.color_edit_button_rgb(&mut geometry.color);
+            ui.end_row();
+
+            ui.label("Rounding");
+            ui.add(egui::Slider::new(&mut geometry.rounding, 0.0..=1.0));
+            ui.end_row();
+
+            ui.label("Blend");
+            ui.add(egui::Slider::new(&mut geometry.blend, 0.0..=1.0));
+            ui.end_row();
+
+            ui.add(egui::Checkbox::new(&mut geometry.is_subtract, "Subtract"));
+            ui.add_enabled(
+                geometry.is_subtract,
+                egui::Checkbox::new(&mut geometry.color_cut, "Color cut"),
+            )
+            .on_hover_text("color the cut surface with this box's color");
+            ui.end_row();
+        });
+    });
+}
+
 fn place_geometry_tooltop(
     mut contexts: EguiContexts,
     control_mode: Res<controls::ControlMode>,
 ) -> Result {
-    if *control_mode != controls::ControlMode::PlaceGeometry {
+    if !matches!(
+        *control_mode,
+        controls::ControlMode::PlaceGeometry | controls::ControlMode::PlacePart(_)
+    ) {
         return Ok(());
     }
 
@@ -124,7 +423,7 @@ fn place_geometry_tooltop(
     egui::Window::new("Add Geometry Mode")
         .title_bar(false)
         .collapsible(false)
-        .anchor(egui::Align2::CENTER_TOP, [0.0, 10.0])
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
         .show(ctx, |ui| {
             ui.label("Select a spot on the plane to place geometry");
             ui.label("Press esc to cancel");
@@ -133,7 +432,14 @@ fn place_geometry_tooltop(
     Ok(())
 }
 
-fn diagnostics_ui(mut contexts: EguiContexts, diagnostics: Res<DiagnosticsStore>) -> Result {
+fn diagnostics_ui(
+    mut contexts: EguiContexts,
+    diagnostics: Res<DiagnosticsStore>,
+    scene: geometry::ScenePrimitives,
+    bounds: Res<bounds::SceneBounds>,
+    mut quality: ResMut<field_quality::FieldQuality>,
+    mut step_scale: ResMut<rendering::StepScale>,
+) -> Result {
     let ctx = contexts.ctx_mut()?;
 
     let fps = diagnostics
@@ -145,7 +451,7 @@ fn diagnostics_ui(mut contexts: EguiContexts, diagnostics: Res<DiagnosticsStore>
         .and_then(|d| d.smoothed());
 
     egui::Window::new("Performance")
-        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 10.0))
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 40.0))
         .resizable(false)
         .show(ctx, |ui| {
             if let Some(fps) = fps {
@@ -157,7 +463,553 @@ fn diagnostics_ui(mut contexts: EguiContexts, diagnostics: Res<DiagnosticsStore>
             if let Some(ft) = frame_time {
                 ui.label(format!("Frame time: {ft:.2} ms"));
             }
+
+            ui.collapsing("Distance field", |ui| {
+                field_quality_section(ui, &scene, &bounds, &mut quality, &mut step_scale);
+            });
         });
 
     Ok(())
 }
+
+/// Checks the scene's distance field for regions where it overestimates
+/// distance, and sets how far the renderer steps to compensate.
+fn field_quality_section(
+    ui: &mut egui::Ui,
+    scene: &geometry::ScenePrimitives,
+    bounds: &bounds::SceneBounds,
+    quality: &mut field_quality::FieldQuality,
+    step_scale: &mut ResMut<rendering::StepScale>,
+) {
+    // Only touch the resource on edits, changing it updates the material
+    let mut scale = step_scale.0;
+    ui.horizontal(|ui| {
+        ui.label("Step scale");
+        ui.add(egui::Slider::new(&mut scale, 0.1..=1.0));
+    });
+    step_scale.set_if_neq(rendering::StepScale(scale));
+
+    let analyze = ui.add_enabled(bounds.0.is_some(), egui::Button::new("Analyze"));
+    if analyze
+        .on_hover_text("sample the scene for overestimated distances")
+        .clicked()
+        && let Some(scene_bounds) = bounds.0
+    {
+        let scene = sdf::Scene::new(scene.collect());
+
+        quality.report = Some(field_quality::analyze(
+            |p| scene.map_geometry(p).dist,
+            field_quality::analysis_region(scene_bounds),
+            field_quality::RESOLUTION,
+        ));
+    }
+
+    let field_quality::FieldQuality {
+        report: Some(report),
+        show_violations,
+    } = quality
+    else {
+        return;
+    };
+
+    ui.label(format!("Max gradient: {:.3}", report.max_gradient));
+
+    if report.violation_count == 0 {
+        ui.label("No overestimating regions found");
+        return;
+    }
+
+    ui.label(format!(
+        "{} of {} samples over 1",
+        report.violation_count, report.samples
+    ));
+    ui.checkbox(show_violations, "Show regions");
+
+    if ui
+        .button(format!(
+            "Use suggested step scale ({:.2})",
+            report.suggested_step_scale
+        ))
+        .clicked()
+    {
+        step_scale.0 = report.suggested_step_scale;
+    }
+}
+
+fn export_dialog_ui(
+    mut contexts: EguiContexts,
+    mut dialog: ResMut<export::ExportDialog>,
+    scene: geometry::ScenePrimitives,
+    bounds: Res<bounds::SceneBounds>,
+    selected_boxes: Query<&geometry::BoxGeometry, With<selection::Selected>>,
+    selected_instances: Query<&parts::PartInstance, With<selection::Selected>>,
+) -> Result {
+    if !dialog.open {
+        return Ok(());
+    }
+
+    let ctx = contexts.ctx_mut()?;
+    let dialog = dialog.as_mut();
+    let mut open = true;
+
+    // Only tracked while it matters, so selecting doesn't discard checks
+    // of the whole scene
+    let mut selection = Vec::new();
+    if dialog.options.scope != export::Scope::Scene {
+        selection.extend(selected_boxes.iter().map(|b| b.id));
+        selection.extend(selected_instances.iter().map(|instance| instance.id));
+        selection.sort();
+    }
+    dialog.options.selection = selection;
+
+    // A checked mesh is only saved while it matches the options, changes to
+    // the scene are caught even while the dialog is closed
+    if dialog
+        .prepared
+        .as_ref()
+        .is_some_and(|prepared| prepared.options != dialog.options)
+    {
+        dialog.discard_check();
+    }
+
+    egui::Window::new("Export…")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("export").show(ui, |ui| {
+                ui.label("File");
+                ui.text_edit_singleline(&mut dialog.path);
+                ui.end_row();
+
+                ui.label("Format");
+                let mut format = dialog.options.format;
+                egui::ComboBox::from_id_salt("export_format")
+                    .selected_text(format.label())
+                    .show_ui(ui, |ui| {
+                        for option in export::Format::ALL {
+                            ui.selectable_value(&mut format, option, option.label());
+                        }
+                    });
+                dialog.set_format(format);
+                ui.end_row();
+
+                ui.label("Nodes");
+                egui::ComboBox::from_id_salt("export_scope")
+                    .selected_text(dialog.options.scope.label())
+                    .show_ui(ui, |ui| {
+                        for scope in export::Scope::ALL {
+                            ui.selectable_value(&mut dialog.options.scope, scope, scope.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("with cuts, the other nodes still cut into the selection");
+                ui.end_row();
+
+                let format = dialog.options.format;
+                if let Some(range) = format.resolution_range() {
+                    ui.label("Resolution");
+                    ui.add(egui::Slider::new(&mut dialog.options.resolution, range))
+                        .on_hover_text("cells along the longest side of the scene");
+                    ui.end_row();
+                } else {
+                    layer_options_ui(ui, &mut dialog.options.layers);
+                }
+
+                match format {
+                    export::Format::Section(_) => {
+                        section_options_ui(ui, &mut dialog.options.section, bounds.0);
+                    }
+                    export::Format::Points(_) => point_options_ui(ui, &mut dialog.options.points),
+                    export::Format::Mesh(_) | export::Format::Volume(_) => {}
+                }
+
+                if format.records_units() {
+                    ui.label("Units");
+                    egui::ComboBox::from_id_salt("export_unit")
+                        .selected_text(dialog.options.unit.label())
+                        .show_ui(ui, |ui| {
+                            for unit in export::Unit::ALL {
+                                ui.selectable_value(&mut dialog.options.unit, unit, unit.label());
+                            }
+                        })
+                        .response
+                        .on_hover_text("the length of one world unit");
+                    ui.end_row();
+                }
+
+                if let export::Format::Mesh(format) = format {
+                    mesh_options_ui(ui, &mut dialog.options, format);
+                }
+            });
+
+            let nodes = || scene.collect_nodes();
+            let has_geometry = bounds.0.is_some();
+
+            ui.horizontal(|ui| {
+                if let Some(fraction) = dialog
+                    .pending
+                    .as_ref()
+                    .map(|pending| pending.progress.fraction())
+                {
+                    ui.add(
+                        egui::ProgressBar::new(fraction)
+                            .desired_width(160.0)
+                            .show_percentage()
+                            .animate(true),
+                    );
+                    if ui.button("Cancel").clicked() {
+                        dialog.cancel();
+                    }
+                    return;
+                }
+
+                match dialog.options.format {
+                    export::Format::Mesh(format) => {
+                        if ui
+                            .add_enabled(has_geometry, egui::Button::new("Check"))
+                            .on_hover_text("mesh the scene and look for problems before saving")
+                            .on_disabled_hover_text("the scene is empty")
+                            .clicked()
+                        {
+                            dialog.start_check(nodes(), format);
+                        }
+
+                        if ui
+                            .add_enabled(dialog.prepared.is_some(), egui::Button::new("Save"))
+                            .on_disabled_hover_text("check the mesh first")
+                            .clicked()
+                            && let Some(prepared) = dialog.prepared.clone()
+                        {
+                            // The checked mesh is written, not the scene
+                            dialog.start_export(Vec::new(), move |_, _, path, progress| {
+                                let summary = export::write(&prepared, path, progress)?;
+                                Ok(match summary.files {
+                                    1 => format!("Wrote {} triangles", summary.triangles),
+                                    files => format!(
+                                        "Wrote {} triangles to {files} files",
+                                        summary.triangles
+                                    ),
+                                })
+                            });
+                        }
+                    }
+                    export::Format::Volume(format) => {
+                        if export_button(ui, has_geometry) {
+                            dialog.start_export(nodes(), move |nodes, options, path, progress| {
+                                let summary =
+                                    export::export_volume(nodes, options, format, path, progress)?;
+                                let [x, y, z] = summary.dims.to_array();
+
+                                Ok(if format == export::VolumeFormat::LayerStack {
+                                    format!("Wrote {y} layers of {x}×{z} pixels")
+                                } else {
+                                    format!("Wrote {x}×{y}×{z} samples")
+                                })
+                            });
+                        }
+                    }
+                    export::Format::Section(format) => {
+                        if export_button(ui, has_geometry) {
+                            dialog.start_export(nodes(), move |nodes, options, path, progress| {
+                                let summary =
+                                    export::export_section(nodes, options, format, path, progress)?;
+                                Ok(format!("Wrote {} outlines", summary.contours))
+                            });
+                        }
+                    }
+                    export::Format::Points(format) => {
+                        if export_button(ui, has_geometry) {
+                            dialog.start_export(nodes(), move |nodes, options, path, progress| {
+                                let summary =
+                                    export::export_points(nodes, options, format, path, progress)?;
+                                Ok(format!("Wrote {} points", summary.points))
+                            });
+                        }
+                    }
+                }
+            });
+
+            if let Some(prepared) = &dialog.prepared {
+                mesh_report_ui(ui, &prepared.report);
+                ui.checkbox(&mut dialog.show_preview, "Show wireframe");
+            }
+
+            match &dialog.status {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(message)) => {
+                    ui.colored_label(egui::Color32::RED, message);
+                }
+                None => {}
+            }
+        });
+
+    dialog.open = open;
+    if !open {
+        dialog.cancel();
+    }
+
+    Ok(())
+}
+
+/// The button writing formats that aren't checked first.
+fn export_button(ui: &mut egui::Ui, has_geometry: bool) -> bool {
+    ui.add_enabled(has_geometry, egui::Button::new("Export"))
+        .on_disabled_hover_text("the scene is empty")
+        .clicked()
+}
+
+/// Asks where to open a scene from or save it to. The file is read or
+/// written once the frame's systems have run.
+fn scene_file_dialog_ui(
+    mut contexts: EguiContexts,
+    mut dialog: ResMut<scene_file::SceneFileDialog>,
+    mut commands: Commands,
+) -> Result {
+    let Some(action) = dialog.action else {
+        return Ok(());
+    };
+
+    let ctx = contexts.ctx_mut()?;
+    let mut open = true;
+
+    let (title, button) = match action {
+        scene_file::FileAction::Open => ("Open Scene", "Open"),
+        scene_file::FileAction::Save => ("Save Scene", "Save"),
+    };
+
+    egui::Window::new(title)
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File");
+                ui.text_edit_singleline(&mut dialog.path);
+            });
+
+            if action == scene_file::FileAction::Open {
+                ui.label("Opening replaces the current scene.");
+            }
+
+            if ui.button(button).clicked() {
+                commands.queue(move |world: &mut World| {
+                    scene_file::SceneFileDialog::run(world, action);
+                });
+            }
+
+            if let Some(error) = &dialog.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+
+    if !open {
+        dialog.action = None;
+    }
+
+    Ok(())
+}
+
+/// Rows of the export dialog setting how many points are scattered.
+fn point_options_ui(ui: &mut egui::Ui, points: &mut export::PointSampling) {
+    ui.label("Points");
+    ui.add(
+        egui::DragValue::new(&mut points.count)
+            .speed(1000.0)
+            .range(1..=10_000_000),
+    )
+    .on_hover_text("how many points to scatter over the surface");
+    ui.end_row();
+
+    ui.label("Seed");
+    ui.add(egui::DragValue::new(&mut points.seed))
+        .on_hover_text("the same seed scatters the same points");
+    ui.end_row();
+}
+
+/// Rows of the export dialog setting the size of layers and their pixels.
+fn layer_options_ui(ui: &mut egui::Ui, layers: &mut export::LayerSlicing) {
+    ui.label("Layer height");
+    ui.add(
+        egui::DragValue::new(&mut layers.layer_height)
+            .speed(0.001)
+            .range(export::LAYER_SIZE_RANGE),
+    )
+    .on_hover_text("thickness of each layer, in world units");
+    ui.end_row();
+
+    ui.label("Pixel size");
+    ui.add(
+        egui::DragValue::new(&mut layers.pixel_size)
+            .speed(0.001)
+            .range(export::LAYER_SIZE_RANGE),
+    )
+    .on_hover_text("width of each pixel, in world units");
+    ui.end_row();
+}
+
+/// Rows of the export dialog placing the section plane, within the scene's
+/// bounds when there are any.
+fn section_options_ui(
+    ui: &mut egui::Ui,
+    section: &mut export::SectionPlane,
+    bounds: Option<bevy::math::bounding::Aabb3d>,
+) {
+    ui.label("Plane");
+    ui.horizontal(|ui| {
+        for (axis, label) in export::SectionPlane::AXIS_LABELS.into_iter().enumerate() {
+            ui.radio_value(&mut section.axis, axis, label);
+        }
+    })
+    .response
+    .on_hover_text("the axis the section plane is cut across");
+    ui.end_row();
+
+    let mut offset = egui::DragValue::new(&mut section.offset).speed(0.01);
+    if let Some(bounds) = bounds {
+        offset = offset.range(bounds.min[section.axis]..=bounds.max[section.axis]);
+    }
+
+    ui.label("Offset");
+    ui.add(offset)
+        .on_hover_text("where the plane crosses its axis");
+    ui.end_row();
+}
+
+/// Rows of the export dialog that only apply when exporting a mesh.
+fn mesh_options_ui(
+    ui: &mut egui::Ui,
+    options: &mut export::ExportOptions,
+    format: export::MeshFormat,
+) {
+    ui.label("Edges");
+    ui.horizontal(|ui| {
+        for mesher in export::Mesher::ALL {
+            ui.radio_value(&mut options.mesher, mesher, mesher.label());
+        }
+    })
+    .response
+    .on_hover_text("sharp keeps crisp edges, adaptive also merges flat areas");
+    ui.end_row();
+
+    if options.mesher == export::Mesher::Adaptive {
+        ui.label("Max error");
+        ui.add(
+            egui::DragValue::new(&mut options.max_error)
+                .speed(0.001)
+                .range(0.0001..=1.0),
+        )
+        .on_hover_text("how far merging cells may move the surface");
+        ui.end_row();
+    }
+
+    ui.label("Bodies");
+    ui.vertical(|ui| {
+        if format.supports_bodies() {
+            ui.checkbox(&mut options.separate_bodies, "Node per body")
+                .on_hover_text("mesh each scene node as its own named object");
+        }
+        ui.checkbox(&mut options.file_per_body, "File per body")
+            .on_hover_text("write each scene node to its own file, named after the node");
+    });
+    ui.end_row();
+
+    ui.label("Smoothing");
+    ui.horizontal(|ui| {
+        for smoothing in export::Smoothing::ALL {
+            ui.radio_value(&mut options.smoothing, smoothing, smoothing.label());
+        }
+    })
+    .response
+    .on_hover_text("taubin smooths without shrinking the mesh");
+    ui.end_row();
+
+    if options.smoothing != export::Smoothing::Off {
+        ui.label("Iterations");
+        ui.add(egui::Slider::new(&mut options.smoothing_iterations, 1..=50));
+        ui.end_row();
+    }
+
+    ui.label("Decimate");
+    let mut decimate = options.decimation.is_some();
+    if ui
+        .checkbox(&mut decimate, "Reduce triangles")
+        .on_hover_text("merge triangles where the surface allows")
+        .changed()
+    {
+        options.decimation = decimate.then(export::Decimation::default);
+    }
+    ui.end_row();
+
+    if let Some(decimation) = &mut options.decimation {
+        ui.label("Target triangles");
+        ui.add(
+            egui::DragValue::new(&mut decimation.target_triangles)
+                .speed(100)
+                .range(4..=10_000_000),
+        );
+        ui.end_row();
+
+        ui.label("Decimation error");
+        ui.add(
+            egui::DragValue::new(&mut decimation.max_error)
+                .speed(0.001)
+                .range(0.0001..=1.0),
+        )
+        .on_hover_text("how far decimation may move the surface");
+        ui.end_row();
+    }
+}
+
+fn mesh_report_ui(ui: &mut egui::Ui, report: &export::MeshReport) {
+    let check = |ui: &mut egui::Ui, ok: bool, text: String| {
+        if ok {
+            ui.label(text);
+        } else {
+            ui.colored_label(egui::Color32::RED, text);
+        }
+    };
+
+    egui::Grid::new("mesh_report").show(ui, |ui| {
+        ui.label("Triangles");
+        ui.label(report.triangles.to_string());
+        ui.end_row();
+
+        ui.label("Watertight");
+        check(
+            ui,
+            report.is_watertight(),
+            format!("{} open edges", report.boundary_edges),
+        );
+        ui.end_row();
+
+        ui.label("Manifold");
+        check(
+            ui,
+            report.is_manifold(),
+            format!(
+                "{} edges, {} vertices shared by too many faces",
+                report.non_manifold_edges, report.non_manifold_vertices
+            ),
+        );
+        ui.end_row();
+
+        ui.label("Self-intersections");
+        check(
+            ui,
+            report.self_intersections == 0,
+            report.self_intersections.to_string(),
+        );
+        ui.end_row();
+
+        ui.label("Degenerate triangles");
+        check(
+            ui,
+            report.degenerate_triangles == 0,
+            report.degenerate_triangles.to_string(),
+        );
+        ui.end_row();
+    });
+}